@@ -0,0 +1,31 @@
+//! Times `get_wall_view` over a walk through a medium and a large maze, e.g.
+//! `cargo bench-walls`. The total of the middle column's distances is printed so that
+//! changes to the ray caster can be checked for the same result as well as speed.
+
+use maze_racer::state::{MazeSize, State};
+use maze_racer::maze::Algorithm;
+use maze_racer::view::get_wall_view;
+use rand::{rngs::SmallRng, SeedableRng};
+use std::time::Instant;
+
+const FRAMES: usize = 2000;
+
+fn main() {
+    for size in [MazeSize::Medium, MazeSize::Large] {
+        let mut state = State::new();
+        state.generate_maze(&mut SmallRng::seed_from_u64(5), size, Algorithm::RecursiveBacktracker, 0.5);
+        let start = Instant::now();
+        let mut total = 0.0;
+        for frame in 0..FRAMES {
+            let angle = frame as f32 * 0.01;
+            let x = 0.5 + (frame % state.width) as f32;
+            let y = 0.5 + ((frame / state.width) % state.height) as f32;
+            let walls = get_wall_view(angle, x, y, &state.horizontal_walls, &state.vertical_walls, &state.exits);
+            total += walls[80].1;
+        }
+        println!(
+            "{}x{}: {:?} per frame (total {total})",
+            state.width, state.height, start.elapsed() / FRAMES as u32
+        );
+    }
+}
@@ -1,3 +1,4 @@
+use crate::constants::{BULLET_SPEED, BULLETS_PER_PLAYER, RELOAD_TIME};
 
 #[derive(Clone, Copy, PartialEq)]
 pub enum Ammo {
@@ -5,24 +6,112 @@ pub enum Ammo {
     Reloading(u8)
 }
 
+#[derive(Clone, Copy, PartialEq)]
+pub enum Weapon {
+    Pistol,
+    /// Several pellets at once, spread out and short-lived.
+    Shotgun,
+    /// A slow round that takes off more life.
+    Heavy,
+    /// Fast and accurate, but one shot at a time.
+    Sniper
+}
+
+/// What a weapon's bullets do and how quickly it can fire them.
+#[derive(Clone, Copy, PartialEq)]
+pub struct WeaponStats {
+    /// Distance each bullet travels per frame.
+    pub speed: f32,
+    /// Life taken by each bullet that hits.
+    pub damage: i32,
+    /// Bullets fired per shot.
+    pub pellets: usize,
+    /// Angle the pellets are spread across, in radians.
+    pub spread: f32,
+    /// Frames to reload each round.
+    pub reload: u8,
+    /// Rounds that can be loaded at once (no more than BULLETS_PER_PLAYER).
+    pub magazine: usize,
+    /// How far a bullet goes before it drops.
+    pub range: f32
+}
+
+impl Weapon {
+    pub fn stats(self) -> WeaponStats {
+        match self {
+            Weapon::Pistol => WeaponStats {
+                speed: BULLET_SPEED, damage: 1, pellets: 1, spread: 0.0,
+                reload: RELOAD_TIME, magazine: BULLETS_PER_PLAYER, range: 10.0
+            },
+            Weapon::Shotgun => WeaponStats {
+                speed: BULLET_SPEED * 1.2, damage: 1, pellets: 3, spread: 0.3,
+                reload: 200, magazine: 2, range: 3.0
+            },
+            Weapon::Heavy => WeaponStats {
+                speed: BULLET_SPEED * 0.5, damage: 3, pellets: 1, spread: 0.0,
+                reload: RELOAD_TIME, magazine: 2, range: 8.0
+            },
+            Weapon::Sniper => WeaponStats {
+                speed: BULLET_SPEED * 3.0, damage: 2, pellets: 1, spread: 0.0,
+                reload: RELOAD_TIME, magazine: 1, range: 64.0
+            }
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Weapon::Pistol => "Pistol",
+            Weapon::Shotgun => "Shotgun",
+            Weapon::Heavy => "Heavy",
+            Weapon::Sniper => "Sniper"
+        }
+    }
+
+    /// The weapon after this one, for cycling through them all.
+    pub fn next(self) -> Weapon {
+        match self {
+            Weapon::Pistol => Weapon::Shotgun,
+            Weapon::Shotgun => Weapon::Heavy,
+            Weapon::Heavy => Weapon::Sniper,
+            Weapon::Sniper => Weapon::Pistol
+        }
+    }
+}
+
 #[derive(Clone, Copy, PartialEq)]
 pub struct Bullet {
     pub x: f32,
     pub y: f32,
     pub owner: usize,
     pub angle: f32,
-    pub inflight: bool
+    pub inflight: bool,
+    pub speed: f32,
+    pub damage: i32,
+    /// Distance left before the bullet drops.
+    pub range: f32,
+    /// Walls the bullet can still bounce off before a wall stops it.
+    pub bounces: u8
 }
 
 impl Bullet {
 
+    /// A pistol round.
     pub fn new(x: f32, y: f32, owner: usize, angle: f32, inflight: bool) -> Bullet {
+        Bullet::from_weapon(Weapon::Pistol, x, y, owner, angle, inflight)
+    }
+
+    pub fn from_weapon(weapon: Weapon, x: f32, y: f32, owner: usize, angle: f32, inflight: bool) -> Bullet {
+        let stats = weapon.stats();
         Bullet {
             x,
             y,
             owner,
             angle,
-            inflight
+            inflight,
+            speed: stats.speed,
+            damage: stats.damage,
+            range: stats.range,
+            bounces: 0
         }
     }
 }
\ No newline at end of file
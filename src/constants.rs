@@ -1,20 +1,30 @@
 use core::f32::consts::PI;
 
-pub const WIDTH: usize = 13; // number of horizontal cells in maze
-pub const HEIGHT: usize = 13; // number of vertical cells in maze
-pub const NUM_CELLS: usize = WIDTH * HEIGHT;
-pub const MAX_PASSAGES: usize = NUM_CELLS; // memory to reserve for maze
+pub const MAX_WIDTH: usize = 32; // most horizontal cells in a maze (one bit per cell in a u32)
+pub const MAX_HEIGHT: usize = 32; // most vertical cells in a maze
+pub const MAX_CELLS: usize = MAX_WIDTH * MAX_HEIGHT;
+pub const MAX_PASSAGES: usize = MAX_CELLS + MAX_CELLS / 2; // memory to reserve for maze, including loops
+pub const MAX_EXITS: usize = 4; // the exit is the center cell (or cells, when a dimension is even)
 
 pub const FOV: f32 = PI / 2.7; // The player's field of view.
 pub const HALF_FOV: f32 = FOV * 0.5; // Half the player's field of view.
 pub const ANGLE_STEP: f32 = FOV / 160.0; // The angle between each ray.
 pub const WALL_HEIGHT: f32 = 80.0; // A magic number.
 pub const STEP_SIZE: f32 = 0.045;
+pub const FRAME_RATE: u32 = 60; // WASM-4 calls update() 60 times a second
 
 pub const NUM_PLAYERS: usize = 4;
 pub const PLAYER_WIDTH: f32 = 0.5;
+pub const PLAYER_RADIUS: f32 = 0.2; // how close a player can get to a wall (keep well under half a cell)
+pub const MAX_LIFE: i32 = 5;
 
 pub const BULLETS_PER_PLAYER: usize = 5;
-pub const NUM_BULLETS: usize = NUM_PLAYERS * BULLETS_PER_PLAYER;
+pub const NUM_BULLETS: usize = NUM_PLAYERS * BULLETS_PER_PLAYER * 2; // room for shotgun spreads
 pub const RELOAD_TIME: u8 = 255;
-pub const BULLET_SPEED: f32 = 0.05;
\ No newline at end of file
+pub const BULLET_SPEED: f32 = 0.05;
+pub const MAX_BOUNCES: u8 = 3; // how many walls a bullet can glance off when ricochets are on
+
+pub const NUM_PICKUPS: usize = 6; // two of each kind
+pub const PICKUP_SIZE: f32 = 0.25;
+pub const PICKUP_RESPAWN: u32 = 15 * FRAME_RATE;
+pub const MAX_SPARE_MAGAZINES: u8 = 2;
\ No newline at end of file
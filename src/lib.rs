@@ -1,72 +1,344 @@
 // Initially based on [wasm4-raycaster](https://github.com/grantshandy/wasm4-raycaster)
 // which carries an MIT License and is Copyright (c) 2023 Grant Handy.
 
-#![no_std]
+#![cfg_attr(not(feature = "headless"), no_std)]
 
-mod constants;
-mod state;
-mod util;
-mod view;
-mod wasm4;
-mod arms;
+pub mod constants;
+pub mod state;
+pub mod util;
+pub mod view;
+pub mod maze;
+pub mod menu;
+pub mod save;
+pub mod wasm4;
+pub mod arms;
+pub mod pickups;
+pub mod textures;
 
 use heapless::{String};
-use rand::{rngs::SmallRng, SeedableRng};
-use util::point_in_wall;
+use rand::{rngs::SmallRng, Rng, SeedableRng};
+use util::{point_in_wall, get_center_from_index};
 use wasm4::{
-    DRAW_COLORS, BLIT_1BPP, NETPLAY, PALETTE,
-    GAMEPAD1, GAMEPAD2, GAMEPAD3, GAMEPAD4,
-    BUTTON_UP, BUTTON_DOWN,
-    BUTTON_LEFT, BUTTON_RIGHT,
+    DRAW_COLORS, BLIT_1BPP, NETPLAY, PALETTE, FRAMEBUFFER,
+    GAMEPAD1, GAMEPAD2, GAMEPAD3, GAMEPAD4, MOUSE_X,
     BUTTON_1, BUTTON_2,
-    vline, oval, rect, blit, line, diskr, trace, text
+    oval, rect, blit, line, diskr, diskw, text
 };
 use core::{f32::consts::PI, fmt::Write};
 use libm::{atan2f, fabsf, floorf};
 
-use state::{State, View};
-use constants::{WIDTH, HEIGHT, NUM_PLAYERS};
+use state::{State, View, GameMode, MazeSize, Difficulty, ControlScheme, Controller, Options};
+use constants::{NUM_PLAYERS, FRAME_RATE};
 
-use view::{get_wall_view, get_bullet_view, get_ammo_view, get_player_view};
+use maze::Algorithm;
+use menu::{Menu, MenuEvent, cycle};
+use save::{SaveData, SAVE_SIZE};
+use view::{
+    get_wall_view, get_bullet_view, get_ammo_view, get_player_view, get_pickup_view,
+    draw_floor_and_ceiling, draw_sprite, in_circle, set_pixel, fog_level, fogged
+};
+use pickups::PickupKind;
+use textures::{texel, BRICKS, BLOCKS};
+
+// A panic stops the cart, leaving a note in the WASM-4 console
+#[cfg(not(feature = "headless"))]
+#[panic_handler]
+fn panic_handler(_info: &core::panic::PanicInfo) -> ! {
+    wasm4::trace("panic");
+    core::arch::wasm32::unreachable()
+}
 
-static mut STATE: State = State::new();
+/// The screens the cart moves through.
+#[derive(Clone, Copy, PartialEq)]
+enum Phase {
+    Title,
+    ModeSelect,
+    Players,
+    Playing,
+    Paused,
+    RoundOver,
+    Results
+}
+
+// Starts out as zeroes, which keep it out of the cart's data; every round begins with a reset
+static mut STATE: State = unsafe { core::mem::zeroed() };
+static mut SAVE: SaveData = SaveData::new();
+static mut OPTIONS: Options = Options::new();
+// Seed for the current maze; starts as the one on disk
+static mut SEED: u64 = 0;
+static mut PHASE: Phase = Phase::Title;
+// Frames spent in the current phase, and the menu shown in it (if any)
+static mut PHASE_FRAMES: u32 = 0;
+static mut MENU: Menu = Menu::new();
+// Where player 1's score landed in the high-score table
+static mut NEW_HIGH_SCORE: Option<usize> = None;
+// Menus are driven by player 1
 static mut PREVIOUS_GAMEPAD1: u8 = 0;
-static mut PREVIOUS_GAMEPAD2: u8 = 0;
-static mut PREVIOUS_GAMEPAD3: u8 = 0;
-static mut PREVIOUS_GAMEPAD4: u8 = 0;
+// Frames player 1 has been holding both buttons
+static mut BOTH_HELD: u32 = 0;
+
+// How long to keep showing the game before switching to the results screen
+const RESULTS_DELAY: u32 = 2 * FRAME_RATE;
+
+// The choices offered on the mode select screen
+const MODES: [GameMode; 3] = [GameMode::Deathmatch, GameMode::Race, GameMode::FreeForAll];
+const SIZES: [MazeSize; 3] = [MazeSize::Small, MazeSize::Medium, MazeSize::Large];
+const ALGORITHMS: [Algorithm; 5] = [
+    Algorithm::RecursiveBacktracker,
+    Algorithm::Prim,
+    Algorithm::Kruskal,
+    Algorithm::Eller,
+    Algorithm::BinaryTree
+];
+const BRAIDS: [f32; 4] = [0.0, 0.25, 0.5, 1.0];
+const RICOCHETS: [bool; 2] = [false, true];
+const CONTROLLERS: [Controller; 2] = [Controller::Human, Controller::Bot];
+const DROP_INS: [bool; 2] = [false, true];
+const CONTROLS: [ControlScheme; 3] = [ControlScheme::Classic, ControlScheme::Strafe, ControlScheme::Mouse];
+const DIFFICULTIES: [Difficulty; 4] = [Difficulty::Easy, Difficulty::Normal, Difficulty::Hard, Difficulty::Nightmare];
+
+// With strafe controls button 2 is held down a lot, so pausing takes a deliberate hold
+const PAUSE_HOLD: u32 = FRAME_RATE / 2;
+
+// Mouse look: how far from the middle of the screen the pointer can be before the view
+// turns, and how fast it turns per pixel beyond that
+const MOUSE_DEAD_ZONE: i32 = 12;
+const MOUSE_TURN: f32 = 0.0015;
+
+// Width of the top-down map in pixels, whatever the size of the maze
+const MAP_SIZE: f32 = 130.0;
 
 #[no_mangle]
 unsafe fn start() {
 
     *PALETTE = [0xfff6d3, 0xeb6b6f, 0xf9a875, 0x7c3f58];
 
-    let mut buffer = [0u8; core::mem::size_of::<i32>()];
+    // All randomness in the game derives from the seed, which netplay peers share via the disk
+    let mut buffer = [0u8; SAVE_SIZE];
     diskr(buffer.as_mut_ptr(), buffer.len() as u32);
-    let seed = u32::from_le_bytes(buffer);
-
-    let mut RNG = SmallRng::seed_from_u64(seed as u64);
-    STATE.generate_maze(&mut RNG);
+    SAVE = SaveData::from_bytes(&buffer);
+    SEED = SAVE.seed as u64;
+    OPTIONS.controls = SAVE.controls;
 }
 
 #[no_mangle]
 unsafe fn update() {
 
-    STATE.update(
-        *GAMEPAD1 & BUTTON_UP != 0,
-        *GAMEPAD1 & BUTTON_DOWN != 0,
-        *GAMEPAD1 & BUTTON_LEFT != 0,
-        *GAMEPAD1 & BUTTON_RIGHT != 0,
-        *GAMEPAD1 & (*GAMEPAD1 ^ PREVIOUS_GAMEPAD1) & BUTTON_1 != 0,
-        *GAMEPAD1 & (*GAMEPAD1 ^ PREVIOUS_GAMEPAD1) & BUTTON_2 != 0
-    );
+    let gamepad = *GAMEPAD1;
+    let pressed = gamepad & (gamepad ^ PREVIOUS_GAMEPAD1);
+    PREVIOUS_GAMEPAD1 = gamepad;
+    PHASE_FRAMES += 1;
+
+    match PHASE {
+        Phase::Title => {
+            draw_title();
+            if pressed & BUTTON_1 != 0 {
+                set_phase(Phase::ModeSelect);
+            }
+        },
+        Phase::ModeSelect => {
+            select_mode(pressed);
+        },
+        Phase::Players => {
+            select_players(pressed);
+        },
+        Phase::Playing => {
+            // Pressing both buttons together pauses the game
+            let both = BUTTON_1 | BUTTON_2;
+            BOTH_HELD = if gamepad & both == both { BOTH_HELD + 1 } else { 0 };
+            let pause = match STATE.controls {
+                ControlScheme::Strafe => BOTH_HELD == PAUSE_HOLD,
+                _ => gamepad & both == both && pressed & both != 0
+            };
+            if pause {
+                set_phase(Phase::Paused);
+                draw_pause_menu();
+                return;
+            }
+
+            if STATE.controls == ControlScheme::Mouse {
+                let offset = *MOUSE_X as i32 - 80;
+                if offset.abs() > MOUSE_DEAD_ZONE {
+                    let beyond = offset - MOUSE_DEAD_ZONE * offset.signum();
+                    STATE.look(0, -(beyond as f32) * MOUSE_TURN);
+                }
+            }
+            STATE.update([*GAMEPAD1, *GAMEPAD2, *GAMEPAD3, *GAMEPAD4]);
+
+            // Record the score as soon as player 1's round is over
+            if STATE.round_over() {
+                NEW_HIGH_SCORE = SAVE.record(STATE.score, STATE.mode, STATE.size);
+                let bytes = SAVE.to_bytes();
+                diskw(bytes.as_ptr(), bytes.len() as u32);
+                set_phase(Phase::RoundOver);
+            }
+            draw_game();
+        },
+        Phase::Paused => {
+            match MENU.navigate(pressed, 2) {
+                MenuEvent::Select(0) => set_phase(Phase::Playing),
+                MenuEvent::Select(_) => {
+                    // An abandoned round never makes the high-score table
+                    NEW_HIGH_SCORE = None;
+                    set_phase(Phase::Results);
+                },
+                _ => {}
+            }
+            draw_pause_menu();
+        },
+        Phase::RoundOver => {
+            // Everyone else carries on while the result sinks in
+            STATE.update([*GAMEPAD1, *GAMEPAD2, *GAMEPAD3, *GAMEPAD4]);
+            draw_game();
+            if PHASE_FRAMES > RESULTS_DELAY || pressed & BUTTON_1 != 0 {
+                set_phase(Phase::Results);
+            }
+        },
+        Phase::Results => {
+            match MENU.navigate(pressed, 3) {
+                MenuEvent::Select(0) => start_round(),
+                MenuEvent::Select(1) => {
+                    // The next seed follows from the last, so netplay peers agree on it
+                    SEED = SmallRng::seed_from_u64(SEED).gen::<u64>();
+                    start_round();
+                },
+                MenuEvent::Select(_) => set_phase(Phase::ModeSelect),
+                _ => draw_results()
+            }
+        }
+    }
+}
+
+/// Moves to another screen, starting its frame count and menu from scratch.
+unsafe fn set_phase(phase: Phase) {
+    PHASE = phase;
+    PHASE_FRAMES = 0;
+    MENU = Menu::new();
+}
+
+/// Builds the maze for the chosen options and starts playing.
+unsafe fn start_round() {
+    // The mouse isn't shared over netplay, so netplay games fall back to strafing
+    let mut options = OPTIONS;
+    let netplay = *NETPLAY & 0b100 != 0;
+    if options.controls == ControlScheme::Mouse && netplay {
+        options.controls = ControlScheme::Strafe;
+    }
+    // Only player 1's view is drawn on a local screen, so other people need netplay to see
+    if !netplay {
+        options.players[1..].fill(Controller::Bot);
+        options.drop_in = false;
+    }
+    STATE.reset(SEED, options);
+    set_phase(Phase::Playing);
+}
 
-    // TODO: Remove netplay
+unsafe fn draw_title() {
+    *DRAW_COLORS = 0x04;
+    text("MAZE RACER", 40, 56);
+    // Blink the prompt about twice a second
+    if (PHASE_FRAMES / 30).is_multiple_of(2) {
+        *DRAW_COLORS = 0x03;
+        text("Press X to start", 16, 96);
+    }
+}
+
+/// Lets player 1 pick the options for the round; left and right change a setting.
+unsafe fn select_mode(pressed: u8) {
+    match MENU.navigate(pressed, 9) {
+        MenuEvent::Change(0, step) => OPTIONS.mode = cycle(&MODES, OPTIONS.mode, step),
+        MenuEvent::Change(1, step) => OPTIONS.size = cycle(&SIZES, OPTIONS.size, step),
+        MenuEvent::Change(2, step) => OPTIONS.algorithm = cycle(&ALGORITHMS, OPTIONS.algorithm, step),
+        MenuEvent::Change(3, step) => OPTIONS.braid = cycle(&BRAIDS, OPTIONS.braid, step),
+        MenuEvent::Change(4, step) => OPTIONS.difficulty = cycle(&DIFFICULTIES, OPTIONS.difficulty, step),
+        MenuEvent::Change(5, step) => {
+            OPTIONS.controls = cycle(&CONTROLS, OPTIONS.controls, step);
+            SAVE.controls = OPTIONS.controls;
+            let bytes = SAVE.to_bytes();
+            diskw(bytes.as_ptr(), bytes.len() as u32);
+        },
+        MenuEvent::Change(6, step) => OPTIONS.ricochet = cycle(&RICOCHETS, OPTIONS.ricochet, step),
+        MenuEvent::Select(7) => {
+            set_phase(Phase::Players);
+            return;
+        },
+        MenuEvent::Select(8) => {
+            start_round();
+            return;
+        },
+        _ => {}
+    }
+
+    let mut mode = String::<20>::new();
+    write!(mode, "Mode: {}", OPTIONS.mode.name()).unwrap();
+    let mut size = String::<20>::new();
+    write!(size, "Size: {}", OPTIONS.size.name()).unwrap();
+    let mut algorithm = String::<20>::new();
+    write!(algorithm, "Maze: {}", OPTIONS.algorithm.name()).unwrap();
+    let mut braid = String::<20>::new();
+    write!(braid, "Loops: {}%", (OPTIONS.braid * 100.0) as i32).unwrap();
+    let mut difficulty = String::<20>::new();
+    write!(difficulty, "Bots: {}", OPTIONS.difficulty.name()).unwrap();
+    let mut controls = String::<20>::new();
+    write!(controls, "Controls: {}", OPTIONS.controls.name()).unwrap();
+    let ricochet = if OPTIONS.ricochet { "Ricochet: On" } else { "Ricochet: Off" };
+
+    *DRAW_COLORS = 0x04;
+    text("SELECT MODE", 36, 16);
+    MENU.draw(&[&mode, &size, &algorithm, &braid, &difficulty, &controls, ricochet, "Players", "Start"], 12, 44);
+}
+
+/// Lets player 1 choose who drives each slot: a person on that gamepad, or a bot.
+/// People in slots 2 to 4 only take part over netplay.
+unsafe fn select_players(pressed: u8) {
+    match MENU.navigate(pressed, NUM_PLAYERS + 2) {
+        MenuEvent::Change(row, step) if row < NUM_PLAYERS => {
+            OPTIONS.players[row] = cycle(&CONTROLLERS, OPTIONS.players[row], step);
+        },
+        MenuEvent::Change(row, step) if row == NUM_PLAYERS => {
+            OPTIONS.drop_in = cycle(&DROP_INS, OPTIONS.drop_in, step);
+        },
+        MenuEvent::Select(row) if row == NUM_PLAYERS + 1 => {
+            set_phase(Phase::ModeSelect);
+            return;
+        },
+        _ => {}
+    }
+
+    let mut slots: [String<20>; NUM_PLAYERS] = Default::default();
+    for (pidx, slot) in slots.iter_mut().enumerate() {
+        write!(slot, "P{}: {}", pidx + 1, OPTIONS.players[pidx].name()).unwrap();
+    }
+    let drop_in = if OPTIONS.drop_in { "Drop-in: On" } else { "Drop-in: Off" };
+
+    *DRAW_COLORS = 0x04;
+    text("PLAYERS", 52, 16);
+    MENU.draw(&[&slots[0], &slots[1], &slots[2], &slots[3], drop_in, "Back"], 12, 44);
+    if *NETPLAY & 0b100 == 0 {
+        *DRAW_COLORS = 0x03;
+        text("P2-P4 need netplay", 8, 136);
+    }
+}
+
+unsafe fn draw_pause_menu() {
+    *DRAW_COLORS = 0x04;
+    text("PAUSED", 56, 48);
+    MENU.draw(&["Resume", "End round"], 44, 72);
+}
+
+/// Renders the game from the local player's point of view.
+unsafe fn draw_game() {
+
+    // The simulation is identical on every netplay peer; each peer only renders its own player
     let pid = if *NETPLAY & 0b100 != 0 {
         (*NETPLAY & 0b011) as usize
     } else {
         0
     };
 
+    // Exits only mean something in a race
+    let exits: &[usize] = if STATE.mode == GameMode::Race { &STATE.exits } else { &[] };
+
     // Draw either the first person view or the top-down view
     match STATE.player_view[pid] {
         View::FirstPerson => {
@@ -75,7 +347,8 @@ unsafe fn update() {
                 STATE.player_x[pid], 
                 STATE.player_y[pid], 
                 &STATE.horizontal_walls, 
-                &STATE.vertical_walls
+                &STATE.vertical_walls,
+                exits
             );
 
             let bullets = get_bullet_view(
@@ -85,8 +358,16 @@ unsafe fn update() {
                 &STATE.bullets
             );
 
+            let pickups = get_pickup_view(
+                STATE.player_angle[pid],
+                STATE.player_x[pid],
+                STATE.player_y[pid],
+                &STATE.pickups
+            );
+
             let ammunition = get_ammo_view(
-                STATE.player_ammo[pid]
+                STATE.player_ammo[pid],
+                STATE.player_weapon[pid]
             );
 
             let players = get_player_view(
@@ -97,91 +378,89 @@ unsafe fn update() {
                 STATE.player_life
             );
 
-            // Draw walls first
+            // Draw the floor and ceiling first, then walls over them
+            draw_floor_and_ceiling(
+                STATE.player_angle[pid],
+                STATE.player_x[pid],
+                STATE.player_y[pid],
+                &mut *FRAMEBUFFER
+            );
+
             for (x, wall) in walls.iter().enumerate() {
-                let (height, _, shadow) = wall;
-
-                if *shadow {
-                    // draw with color 2 for walls with "shadow"
-                    *DRAW_COLORS = 0x2;
-                } else {
-                    // draw with color 3 for walls without "shadow"
-                    *DRAW_COLORS = 0x3;
+                let (height, distance, shadow, exit, u) = *wall;
+                let level = fog_level(distance);
+
+                // Copy a column of the wall's texture, stretched to the wall's height, with bricks
+                // on walls without "shadow" and blocks on the others. The walls of the exit are
+                // drawn with color 4 so it stands out.
+                let texture = if shadow { &BLOCKS } else { &BRICKS };
+                let top = 80 - (height / 2);
+                for y in top.max(0)..(top + height).min(160) {
+                    let v = (y - top) as f32 / height as f32;
+                    let colour = if exit { 3 } else { texel(texture, u, v) };
+                    set_pixel(&mut *FRAMEBUFFER, x as i32, y, fogged(x as i32, y, colour, level));
                 }
+            }
+
+            // Sprites are clipped column by column against the walls in front of them
+            let depth = walls.map(|(_, distance, _, _, _)| distance);
 
-                vline(x as i32, 80 - (height / 2), *height as u32);
+            // Then pickups lying on the floor
+            for pickup in pickups.iter() {
+                let (h_position, v_position, size, distance, kind) = *pickup;
+                let edge = move |x: u32, y: u32| x == 0 || y == 0 || x + 1 == size || y + 1 == size;
+                match kind {
+                    Some(PickupKind::Health) => {
+                        // A box with a cross on it
+                        let third = size / 3;
+                        let bar = move |a: u32, b: u32| a >= third && a < 2 * third && b >= 1 && b + 1 < size;
+                        draw_sprite(&mut *FRAMEBUFFER, &depth, h_position, v_position, size, size, distance, |x, y| {
+                            Some(if edge(x, y) || bar(x, y) || bar(y, x) { 3 } else { 1 })
+                        });
+                    },
+                    Some(PickupKind::Reload) => {
+                        draw_sprite(&mut *FRAMEBUFFER, &depth, h_position, v_position, size, size, distance, |x, y| {
+                            let inside = x >= 1 && y >= 1 && in_circle(x - 1, y - 1, size.saturating_sub(2));
+                            in_circle(x, y, size).then_some(if inside { 2 } else { 3 })
+                        });
+                    },
+                    Some(PickupKind::Magazine) => {
+                        let width = size / 2;
+                        let left = h_position + (size / 4) as i32;
+                        draw_sprite(&mut *FRAMEBUFFER, &depth, left, v_position, width, size, distance, |x, y| {
+                            Some(if x == 0 || y == 0 || x + 1 == width || y + 1 == size { 2 } else { 3 })
+                        });
+                    },
+                    None => {}
+                }
             }
 
             // Then draw players
             for player in players.iter() {
-                let (h_position, v_position, width, height, distance, facing_me, alive, not_me) = player;
-                if *not_me && *alive {
-                    let x = match *h_position {
-                        0..=159 => *h_position as usize,
-                        _ => 0
-                    };
-                    let (_, wall_distance, _) = walls[x];
-                    // Only draw if not obstructed by a wall
-                    if *distance < wall_distance {
-                        // Body
-                        *DRAW_COLORS = 0x41;
-                        rect(*h_position + ((*height - *width) / 2) as i32, *v_position, *width, *height);
-                        // Only draw the face if they are facing me
-                        if *facing_me {
-                            // Left eye
-                            *DRAW_COLORS = 0x44;
-                            let x = *h_position as f32 + *width as f32 * 1.0 / 8.0;
-                            let y = *v_position as f32 + *height as f32 * 1.0 / 8.0;
-                            let w = *width as f32 / 4.0;
-                            let h = *height as f32 / 4.0;
-                            rect(
-                                x as i32  + ((*height - *width) / 2) as i32, 
-                                y as i32,
-                                w as u32,
-                                h as u32
-                            );
-                            // Right eye
-                            *DRAW_COLORS = 0x44;
-                            let x = *h_position as f32 + *width as f32 * 5.0 / 8.0;
-                            let y = *v_position as f32 + *height as f32 * 1.0 / 8.0;
-                            let w = *width as f32 / 4.0;
-                            let h = *height as f32 / 4.0;
-                            rect(
-                                x as i32  + ((*height - *width) / 2) as i32, 
-                                y as i32,
-                                w as u32,
-                                h as u32
-                            );
-                            // Mouth
-                            *DRAW_COLORS = 0x44;
-                            let x = *h_position as f32 + *width as f32 * 1.0 / 8.0;
-                            let y = *v_position as f32 + *height as f32 * 5.0 / 8.0;
-                            let w = *width as f32 * 3.0 / 4.0;
-                            let h = *height as f32 * 1.0 / 4.0;
-                            rect(
-                                x as i32  + ((*height - *width) / 2) as i32, 
-                                y as i32,
-                                w as u32,
-                                h as u32
-                            );
-                        }
-                    }
+                let (h_position, v_position, width, height, distance, facing_me, alive, not_me) = *player;
+                if not_me && alive {
+                    let left = h_position + ((height - width) / 2) as i32;
+                    draw_sprite(&mut *FRAMEBUFFER, &depth, left, v_position, width, height, distance, |x, y| {
+                        // Body, with a face only if they are facing me
+                        let outline = x == 0 || y == 0 || x + 1 == width || y + 1 == height;
+                        let eyes = y >= height / 8 && y < height / 8 + height / 4 && (
+                            (x >= width / 8 && x < width / 8 + width / 4) ||
+                            (x >= width * 5 / 8 && x < width * 5 / 8 + width / 4)
+                        );
+                        let mouth = y >= height * 5 / 8 && y < height * 5 / 8 + height / 4 &&
+                            x >= width / 8 && x < width / 8 + width * 3 / 4;
+                        Some(if outline || (facing_me && (eyes || mouth)) { 3 } else { 0 })
+                    });
                 }
             }
 
             // Next draw bullets that are in view
-            *DRAW_COLORS = 0x04;
             for bullet in bullets.iter() {
-                let (h_position, v_position, size, distance, inflight) = bullet;
-                let x = match *h_position {
-                    0..=159 => *h_position as usize,
-                    _ => 0
-                };
-                let (_, wall_distance, _) = walls[x];
-                if *inflight {
-                    if *distance < wall_distance {
-                        oval(*h_position, *v_position, *size, *size);
-                    }
+                let (h_position, v_position, size, distance, inflight) = *bullet;
+                if inflight {
+                    draw_sprite(&mut *FRAMEBUFFER, &depth, h_position, v_position, size, size, distance, |x, y| {
+                        in_circle(x, y, size).then_some(3)
+                    });
                 }
             }
 
@@ -189,7 +468,9 @@ unsafe fn update() {
             *DRAW_COLORS = 0x40;
             for ammo in ammunition.iter() {
                 let (x, y, size, _, _) = *ammo;
-                oval(x, y, size, size);
+                if size > 0 {
+                    oval(x, y, size, size);
+                }
             }
             *DRAW_COLORS = 0x04;
             for ammo in ammunition.iter() {
@@ -198,6 +479,15 @@ unsafe fn update() {
                     oval(x+fix, y+fix, fill, fill);
                 }
             }
+            // Name the weapon under it, with any spare magazines next to it
+            let name = STATE.player_weapon[pid].name();
+            text(name, 158 - 8 * name.len() as i32, 14);
+            let spares = STATE.spare_magazines[pid];
+            if spares > 0 {
+                let mut message = String::<4>::new();
+                write!(message, "+{spares}").unwrap();
+                text(message, 92, 4);
+            }
 
             const HEART_ICON: [u8; 8] = [
                 0b10011001,
@@ -216,38 +506,75 @@ unsafe fn update() {
                 blit(&HEART_ICON, 10*heart, 4, 8, 8, BLIT_1BPP);
             }
 
-            // Draw the score
-            let mut message = String::<32>::new();
-            let score = STATE.score;
-            write!(message, "Score: {score}").unwrap();
-            text(message, 10, 16);
+            match STATE.mode {
+                GameMode::Deathmatch => {
+                    // Draw the score
+                    let mut message = String::<32>::new();
+                    let score = STATE.score;
+                    write!(message, "Score: {score}").unwrap();
+                    text(message, 10, 16);
+                },
+                GameMode::FreeForAll => {
+                    // Draw the score and this player's kills
+                    let mut message = String::<32>::new();
+                    let score = STATE.score;
+                    write!(message, "Score: {score}").unwrap();
+                    text(message, 10, 16);
+                    let mut message = String::<32>::new();
+                    let kills = STATE.kills[pid];
+                    write!(message, "Kills: {kills}").unwrap();
+                    text(message, 10, 26);
+                },
+                GameMode::Race => {
+                    // Draw the race clock, which stops once this player finishes
+                    let mut message = String::<32>::new();
+                    let frames = STATE.finish_time[pid].unwrap_or(STATE.elapsed());
+                    // Tenths of a second, kept to integers to leave float formatting out of the cart
+                    let tenths = frames * 10 / FRAME_RATE;
+                    write!(message, "Time: {}.{}", tenths / 10, tenths % 10).unwrap();
+                    text(message, 10, 16);
+                }
+            }
         },
         View::TopDown => {
-            // NOTE: Right now the top-down view is just a real-time display of the maze.
-            //       But in the future it could be a place for selecting other weapons.
+            // The top-down view shows the maze in real time, and is where button 1 picks a weapon
+            *DRAW_COLORS = 0x04;
+            let mut message = String::<32>::new();
+            let weapon = STATE.player_weapon[pid].name();
+            write!(message, "X: {weapon}").unwrap();
+            text(message, 10, 4);
+            // Fit the whole maze into the middle of the screen
+            let scale = MAP_SIZE / STATE.width.max(STATE.height) as f32;
+            let offset = (160.0 - scale * STATE.width.max(STATE.height) as f32) / 2.0;
             *DRAW_COLORS = 0x04;
             // Horizontal walls
-            for h in 0..=HEIGHT {
+            for h in 0..=STATE.height {
                 let y = h as f32;
-                for w in 0..=WIDTH {
+                for w in 0..=STATE.width {
                     let x = w as f32 + 0.5;
                     if point_in_wall(y, x, &STATE.horizontal_walls) {
-                        line(((x-0.5)*10.0+15.0) as i32, (y*10.0 + 15.0) as i32, ((x+0.5)*10.0+15.0) as i32, (y*10.0 + 15.0) as i32);
+                        line(((x-0.5)*scale+offset) as i32, (y*scale + offset) as i32, ((x+0.5)*scale+offset) as i32, (y*scale + offset) as i32);
                     }
                 }
             }
             *DRAW_COLORS = 0x04;
             // Vertical walls
-            for w in 0..=WIDTH {
+            for w in 0..=STATE.width {
                 let x = w as f32;
-                for h in 0..=HEIGHT {
+                for h in 0..=STATE.height {
                     let y = h as f32 + 0.5;
                     if point_in_wall(x, y, &STATE.vertical_walls) {
-                        line((x*10.0 + 15.0) as i32, ((y-0.5)*10.0+15.0) as i32, (x*10.0 + 15.0) as i32, ((y+0.5)*10.0+15.0) as i32);
+                        line((x*scale + offset) as i32, ((y-0.5)*scale+offset) as i32, (x*scale + offset) as i32, ((y+0.5)*scale+offset) as i32);
                     }
                 }
             }
             *DRAW_COLORS = 0x44;
+            // Exit
+            for exit in exits.iter() {
+                let (x, y) = get_center_from_index(*exit, STATE.width, STATE.height);
+                let size = (scale - 3.0).max(1.0) as u32;
+                rect(((x-0.5)*scale+offset+2.0) as i32, ((y-0.5)*scale+offset+2.0) as i32, size, size);
+            }
             *DRAW_COLORS = 0x04;
             // Players
             for player in 0..NUM_PLAYERS {
@@ -279,9 +606,6 @@ unsafe fn update() {
                         let xp = -1.0 * x;
                         let yp = y;
                         let blit_angle = -1.0 * atan2f(yp, xp);
-                        // let mut data = String::<64>::new();
-                        // write!(data, "y: {yp}, x: {xp}, a: {blit_angle}").unwrap();
-                        // trace(data);
                         let num_wraps = floorf((blit_angle - STATE.player_angle[player])/(2.0 * PI));
                         let unwrapped = blit_angle - 2.0 * PI * num_wraps;
                         let extra_unwrapped = unwrapped - 2.0 * PI;
@@ -322,17 +646,50 @@ unsafe fn update() {
                         }
                         n += 1;
                     }
-                    blit(&player_blit, (STATE.player_x[player]*10.0) as i32 + 15 - 3, (STATE.player_y[player]*10.0) as i32 + 15 - 3, 8, 8, BLIT_1BPP);
+                    blit(&player_blit, (STATE.player_x[player]*scale + offset) as i32 - 3, (STATE.player_y[player]*scale + offset) as i32 - 3, 8, 8, BLIT_1BPP);
+                }
+            }
+            // Pickups that are there to be taken
+            for pickup in STATE.pickups.iter().filter(|p| p.available()) {
+                let (x, y) = ((pickup.x*scale + offset) as i32, (pickup.y*scale + offset) as i32);
+                match pickup.kind {
+                    PickupKind::Health => {
+                        *DRAW_COLORS = 0x22;
+                        rect(x - 1, y - 1, 3, 3);
+                    },
+                    PickupKind::Reload => {
+                        *DRAW_COLORS = 0x33;
+                        oval(x - 1, y - 1, 3, 3);
+                    },
+                    PickupKind::Magazine => {
+                        *DRAW_COLORS = 0x33;
+                        rect(x - 1, y - 2, 2, 4);
+                    }
                 }
             }
             *DRAW_COLORS = 0x44;
             // Bullets
             for bullet in STATE.bullets.iter() {
-                oval((bullet.x*10.0) as i32 + 15, (bullet.y*10.0) as i32 + 15, 1, 1);
+                oval((bullet.x*scale + offset) as i32, (bullet.y*scale + offset) as i32, 1, 1);
+            }
+            if STATE.mode == GameMode::FreeForAll {
+                draw_kill_table(150);
             }
         }
     }
 
+    if STATE.mode == GameMode::FreeForAll && STATE.round_over() {
+        draw_kill_table(96);
+    }
+
+    if let Some(winner) = STATE.winner {
+        *DRAW_COLORS = 0x14;
+        let mut message = String::<32>::new();
+        let player_number = winner + 1;
+        write!(message, "PLAYER {player_number} WINS!").unwrap();
+        text(message, 32, 64);
+    }
+
     if STATE.player_life[pid] <= 0 {
         *DRAW_COLORS = 0x14;
         let mut message = String::<32>::new();
@@ -341,11 +698,44 @@ unsafe fn update() {
         text(message, 40, 72);
         text("ELIMINATED!", 40, 80);
     }
+}
 
-    PREVIOUS_GAMEPAD1 = *GAMEPAD1;
-    PREVIOUS_GAMEPAD2 = *GAMEPAD2;
-    PREVIOUS_GAMEPAD3 = *GAMEPAD3;
-    PREVIOUS_GAMEPAD4 = *GAMEPAD4;
+/// Shows how many kills each player has in a free-for-all.
+unsafe fn draw_kill_table(y: i32) {
+    *DRAW_COLORS = 0x14;
+    let mut message = String::<32>::new();
+    for (player, kills) in STATE.kills.iter().enumerate() {
+        write!(message, "P{}:{} ", player + 1, kills).unwrap();
+    }
+    text(message.trim_end(), 4, y);
 }
 
+/// Shows the final score and the high-score table, highlighting this round's entry.
+unsafe fn draw_results() {
+    *DRAW_COLORS = 0x04;
+    let mut message = String::<32>::new();
+    let score = STATE.score;
+    write!(message, "Score: {score}").unwrap();
+    text(message, 10, 8);
+    text("HIGH SCORES", 10, 22);
 
+    for (rank, high_score) in SAVE.high_scores.iter().enumerate() {
+        *DRAW_COLORS = if NEW_HIGH_SCORE == Some(rank) { 0x02 } else { 0x04 };
+        let mode = match high_score.mode {
+            GameMode::Deathmatch => "DM",
+            GameMode::Race => "RC",
+            GameMode::FreeForAll => "FA"
+        };
+        let size = match high_score.size {
+            MazeSize::Small => "S",
+            MazeSize::Medium => "M",
+            MazeSize::Large => "L"
+        };
+        let mut line = String::<32>::new();
+        let (place, score, run) = (rank + 1, high_score.score, high_score.run);
+        write!(line, "{place:>2} {score:>5} {mode} {size} #{run}").unwrap();
+        text(line, 10, 34 + 8 * rank as i32);
+    }
+
+    MENU.draw(&["Same maze again", "New maze", "Change mode"], 12, 122);
+}
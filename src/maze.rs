@@ -0,0 +1,441 @@
+use heapless::Vec;
+use libm::{floorf, sqrtf};
+use rand::Rng;
+use rand::rngs::SmallRng;
+use rand::seq::SliceRandom;
+
+use crate::constants::{MAX_CELLS, MAX_WIDTH};
+
+/// An opening between two neighboring cells, by index. Indices fit in a `u16`, which
+/// halves the memory a large maze's passages take up.
+pub type Passage = (u16, u16);
+
+/// The different ways of carving passages through a grid of cells.
+/// Every algorithm produces a perfect maze (exactly one route between any two cells).
+#[derive(Clone, Copy, PartialEq)]
+pub enum Algorithm {
+    /// Long, winding corridors with few branches.
+    RecursiveBacktracker,
+    /// Lots of short dead ends branching off everywhere.
+    Prim,
+    /// Evenly spread branches with no overall direction.
+    Kruskal,
+    /// Built one row at a time; feels a lot like Kruskal.
+    Eller,
+    /// Every cell opens north or west, leaving two long open edges.
+    BinaryTree
+}
+
+impl Algorithm {
+    pub fn name(self) -> &'static str {
+        match self {
+            Algorithm::RecursiveBacktracker => "Backtracker",
+            Algorithm::Prim => "Prim",
+            Algorithm::Kruskal => "Kruskal",
+            Algorithm::Eller => "Eller",
+            Algorithm::BinaryTree => "Binary tree"
+        }
+    }
+}
+
+/// Randomly creates the passages between cells of a `width` by `height` maze.
+/// `passages` must have room for every cell; pushes past that are dropped rather than
+/// panicking, which keeps the formatting of a panic message out of the cart.
+pub fn generate_passages<const M: usize, const N: usize>(
+    algorithm: Algorithm,
+    width: usize,
+    height: usize,
+    visited: &mut Vec<bool,M>,
+    passages: &mut Vec<Passage,N>,
+    rng: &mut SmallRng
+) {
+    match algorithm {
+        Algorithm::RecursiveBacktracker => backtracker(width, height, visited, passages, rng),
+        Algorithm::Prim => prim(width, height, passages, rng),
+        Algorithm::Kruskal => kruskal(width, height, passages, rng),
+        Algorithm::Eller => eller(width, height, passages, rng),
+        Algorithm::BinaryTree => binary_tree(width, height, passages, rng)
+    }
+}
+
+/// Walks from the first cell to random unvisited neighbors, backing up whenever it gets stuck.
+/// The way back is kept on an explicit stack, as recursing once per cell would run a large
+/// maze out of stack.
+fn backtracker<const M: usize, const N: usize>(
+    width: usize,
+    height: usize,
+    visited: &mut Vec<bool,M>,
+    passages: &mut Vec<Passage,N>,
+    rng: &mut SmallRng
+) {
+    let mut stack: Vec<u16, MAX_CELLS> = Vec::new();
+    visited[0] = true;
+    stack.push(0).ok();
+    while let Some(&cell) = stack.last() {
+        let unvisited: Vec<usize, 4> = find_neighbors(cell as usize, width, height).into_iter()
+            .flatten()
+            .filter(|&n| !visited[n])
+            .collect();
+        match unvisited.choose(rng) {
+            Some(&next) => {
+                visited[next] = true;
+                passages.push((cell, next as u16)).ok();
+                stack.push(next as u16).ok();
+            },
+            None => {
+                stack.pop();
+            }
+        }
+    }
+}
+
+/// Grows the maze outward from the first cell, each time connecting a random cell on its frontier.
+fn prim<const N: usize>(width: usize, height: usize, passages: &mut Vec<Passage,N>, rng: &mut SmallRng) {
+    const OUTSIDE: u8 = 0;
+    const FRONTIER: u8 = 1;
+    const INSIDE: u8 = 2;
+
+    let mut status: Vec<u8, MAX_CELLS> = Vec::new();
+    status.resize(width * height, OUTSIDE).unwrap();
+    let mut frontier: Vec<u16, MAX_CELLS> = Vec::new();
+
+    let add = |cell: usize, status: &mut Vec<u8, MAX_CELLS>, frontier: &mut Vec<u16, MAX_CELLS>| {
+        status[cell] = INSIDE;
+        for neighbor in find_neighbors(cell, width, height).into_iter().flatten() {
+            if status[neighbor] == OUTSIDE {
+                status[neighbor] = FRONTIER;
+                frontier.push(neighbor as u16).ok();
+            }
+        }
+    };
+    add(0, &mut status, &mut frontier);
+
+    while !frontier.is_empty() {
+        let cell = frontier.swap_remove(rng.gen_range(0..frontier.len())) as usize;
+        let inside: Vec<usize, 4> = find_neighbors(cell, width, height).into_iter()
+            .flatten()
+            .filter(|&n| status[n] == INSIDE)
+            .collect();
+        if let Some(&neighbor) = inside.choose(rng) {
+            passages.push((neighbor as u16, cell as u16)).ok();
+        }
+        add(cell, &mut status, &mut frontier);
+    }
+}
+
+/// Visits every wall in random order, knocking it down if the cells either side are not yet connected.
+fn kruskal<const N: usize>(width: usize, height: usize, passages: &mut Vec<Passage,N>, rng: &mut SmallRng) {
+    // Each candidate wall is a cell and, in the lowest bit, whether it separates that cell
+    // from the one to its right (1) or the one below (0).
+    let mut walls: Vec<u16, { 2 * MAX_CELLS }> = Vec::new();
+    for cell in 0..width * height {
+        if !(cell + 1).is_multiple_of(width) {
+            walls.push((cell << 1 | 1) as u16).ok();
+        }
+        if cell + width < width * height {
+            walls.push((cell << 1) as u16).ok();
+        }
+    }
+    walls.shuffle(rng);
+
+    // Union-find over cells; two cells are connected when they share a root.
+    let mut parent: Vec<u16, MAX_CELLS> = (0..(width * height) as u16).collect();
+    fn root(parent: &mut Vec<u16, MAX_CELLS>, mut cell: usize) -> usize {
+        while parent[cell] as usize != cell {
+            parent[cell] = parent[parent[cell] as usize];
+            cell = parent[cell] as usize;
+        }
+        cell
+    }
+
+    for &wall in walls.iter() {
+        let a = (wall >> 1) as usize;
+        let b = if wall & 1 == 1 { a + 1 } else { a + width };
+        let (root_a, root_b) = (root(&mut parent, a), root(&mut parent, b));
+        if root_a != root_b {
+            parent[root_b] = root_a as u16;
+            passages.push((a as u16, b as u16)).ok();
+        }
+    }
+}
+
+/// Works down the maze a row at a time, only remembering which cells of the current row are connected.
+fn eller<const N: usize>(width: usize, height: usize, passages: &mut Vec<Passage,N>, rng: &mut SmallRng) {
+    const UNASSIGNED: usize = usize::MAX;
+    let mut sets: Vec<usize, MAX_WIDTH> = Vec::new();
+    sets.resize(width, UNASSIGNED).unwrap();
+    let mut next_set = 0;
+
+    for y in 0..height {
+        let last_row = y == height - 1;
+
+        // Cells not joined from above start a set of their own
+        for set in sets.iter_mut() {
+            if *set == UNASSIGNED {
+                *set = next_set;
+                next_set += 1;
+            }
+        }
+
+        // Randomly join neighbors in different sets (the last row joins all of them)
+        for x in 0..width - 1 {
+            if sets[x] != sets[x + 1] && (last_row || rng.gen_bool(0.5)) {
+                passages.push(((x + y * width) as u16, (x + 1 + y * width) as u16)).ok();
+                let (keep, merge) = (sets[x], sets[x + 1]);
+                sets.iter_mut().filter(|s| **s == merge).for_each(|s| *s = keep);
+            }
+        }
+
+        if last_row {
+            break;
+        }
+
+        // Every set carries on down into the next row at least once
+        let mut down = [false; MAX_WIDTH];
+        for d in down.iter_mut().take(width) {
+            *d = rng.gen_bool(0.5);
+        }
+        for x in 0..width {
+            if !(0..width).any(|c| sets[c] == sets[x] && down[c]) {
+                let members: Vec<usize, MAX_WIDTH> = (0..width).filter(|&c| sets[c] == sets[x]).collect();
+                down[*members.choose(rng).unwrap()] = true;
+            }
+        }
+        for x in 0..width {
+            if down[x] {
+                passages.push(((x + y * width) as u16, (x + (y + 1) * width) as u16)).ok();
+            } else {
+                sets[x] = UNASSIGNED;
+            }
+        }
+    }
+}
+
+/// Opens every cell to the north or the west, at random, wherever it can.
+fn binary_tree<const N: usize>(width: usize, height: usize, passages: &mut Vec<Passage,N>, rng: &mut SmallRng) {
+    for cell in 1..width * height {
+        let north = (cell >= width).then(|| cell - width);
+        let west = (!cell.is_multiple_of(width)).then(|| cell - 1);
+        let neighbor = match (north, west) {
+            (Some(n), Some(w)) => if rng.gen_bool(0.5) { n } else { w },
+            (Some(n), None) => n,
+            (None, Some(w)) => w,
+            (None, None) => continue
+        };
+        passages.push((neighbor as u16, cell as u16)).ok();
+    }
+}
+
+/// Removes about `braid` (0.0 to 1.0) of the maze's dead ends by opening them into a neighbor,
+/// creating loops so there is more than one way around. Dead ends are joined to each other
+/// where possible, which removes two at once.
+pub fn braid<const N: usize>(
+    width: usize,
+    height: usize,
+    braid: f32,
+    passages: &mut Vec<Passage,N>,
+    rng: &mut SmallRng
+) {
+    let connected = |passages: &Vec<Passage,N>, a: usize, b: usize| {
+        let (a, b) = (a as u16, b as u16);
+        passages.iter().any(|&(p, q)| (p == a && q == b) || (p == b && q == a))
+    };
+    let mut exits: Vec<u8, MAX_CELLS> = Vec::new();
+    exits.resize(width * height, 0).unwrap();
+    for &(a, b) in passages.iter() {
+        exits[a as usize] += 1;
+        exits[b as usize] += 1;
+    }
+
+    let mut dead_ends: Vec<u16, MAX_CELLS> = (0..(width * height) as u16).filter(|&c| exits[c as usize] == 1).collect();
+    dead_ends.shuffle(rng);
+    let target = (dead_ends.len() as f32 * braid.clamp(0.0, 1.0)) as usize;
+
+    let mut removed = 0;
+    for cell in dead_ends.iter().map(|&c| c as usize) {
+        if removed >= target {
+            break;
+        }
+        // An earlier join may have already opened this one up
+        if exits[cell] != 1 {
+            continue;
+        }
+        let mut candidates: Vec<usize, 4> = find_neighbors(cell, width, height).into_iter()
+            .flatten()
+            .filter(|&n| !connected(passages, cell, n))
+            .collect();
+        candidates.shuffle(rng);
+        let neighbor = candidates.iter().copied().find(|&n| exits[n] == 1).or(candidates.first().copied());
+        if let Some(neighbor) = neighbor {
+            if passages.push((cell as u16, neighbor as u16)).is_err() {
+                break;
+            }
+            removed += if exits[neighbor] == 1 { 2 } else { 1 };
+            exits[cell] += 1;
+            exits[neighbor] += 1;
+        }
+    }
+}
+
+/// Distance given to cells that cannot be reached from any source.
+pub const UNREACHABLE: u16 = u16::MAX;
+
+/// Fills `distances` with the number of steps from each cell to the nearest of `sources`,
+/// following open passages (a breadth-first search). Walking to any neighbor one step
+/// closer is then a shortest route to a source.
+pub fn distance_field<const N: usize, const H: usize, const W: usize>(
+    sources: &[usize],
+    width: usize,
+    height: usize,
+    horizontal_walls: &Vec<u32,H>,
+    vertical_walls: &Vec<u32,W>,
+    distances: &mut Vec<u16,N>
+) {
+    distances.clear();
+    distances.resize(width * height, UNREACHABLE).unwrap();
+
+    // Every cell is queued at most once, so the queue never needs to wrap around
+    let mut queue: Vec<u16, MAX_CELLS> = Vec::new();
+    for &source in sources {
+        if distances[source] == UNREACHABLE {
+            distances[source] = 0;
+            queue.push(source as u16).ok();
+        }
+    }
+
+    let mut head = 0;
+    while head < queue.len() {
+        let cell = queue[head] as usize;
+        head += 1;
+        for neighbor in open_neighbors(cell, width, height, horizontal_walls, vertical_walls) {
+            if distances[neighbor] == UNREACHABLE {
+                distances[neighbor] = distances[cell] + 1;
+                queue.push(neighbor as u16).ok();
+            }
+        }
+    }
+}
+
+/// The cells above, below, left and right of a cell, where they exist.
+pub fn find_neighbors(index: usize, width: usize, height: usize) -> [Option<usize>;4] {
+    let num_cells = width * height;
+    let up = (index >= width).then(|| index - width);
+    let down = (index + width < num_cells).then(|| index + width);
+    let left = (!index.is_multiple_of(width)).then(|| index - 1);
+    let right = (!(index + 1).is_multiple_of(width)).then(|| index + 1);
+    [up, down, left, right]
+}
+
+/// The neighbors of a cell that have no wall in the way.
+pub fn open_neighbors<const H: usize, const W: usize>(
+    index: usize,
+    width: usize,
+    height: usize,
+    horizontal_walls: &Vec<u32,H>,
+    vertical_walls: &Vec<u32,W>
+) -> Vec<usize,4> {
+    find_neighbors(index, width, height).into_iter()
+        .flatten()
+        .filter(|&n| !there_is_a_wall_between(index, n, width, horizontal_walls, vertical_walls))
+        .collect()
+}
+
+/// Converts the passages between cells into the wall bitmasks used by the raycaster.
+/// Bit `x` of `horizontal_walls[y]` is the wall along the top of cell `(x, y)` and
+/// bit `y` of `vertical_walls[x]` is the wall along its left side.
+pub fn find_walls<const N: usize, const H: usize, const W: usize>(
+    width: usize,
+    height: usize,
+    passages: &Vec<Passage,N>,
+    horizontal_walls: &mut Vec<u32,H>,
+    vertical_walls: &mut Vec<u32,W>
+) {
+    // Start with every wall standing...
+    let full_row = if width >= 32 { u32::MAX } else { (0b1 << width) - 1 };
+    let full_column = if height >= 32 { u32::MAX } else { (0b1 << height) - 1 };
+    horizontal_walls.clear();
+    vertical_walls.clear();
+    for _ in 0..=height {
+        horizontal_walls.push(full_row).ok();
+    }
+    for _ in 0..=width {
+        vertical_walls.push(full_column).ok();
+    }
+
+    // ...then knock down the ones that a passage goes through.
+    for &(a, b) in passages.iter() {
+        let (low, high) = if a < b { (a as usize, b as usize) } else { (b as usize, a as usize) };
+        if high - low == width {
+            horizontal_walls[high / width] &= !(0b1 << (high % width));
+        } else {
+            vertical_walls[high % width] &= !(0b1 << (high / width));
+        }
+    }
+}
+
+/// True unless cells `a` and `b` are neighbors with no wall between them.
+pub fn there_is_a_wall_between<const H: usize, const W: usize>(
+    a: usize,
+    b: usize,
+    width: usize,
+    horizontal_walls: &Vec<u32,H>,
+    vertical_walls: &Vec<u32,W>
+) -> bool {
+    let (low, high) = if a < b { (a, b) } else { (b, a) };
+    if high - low == width {
+        horizontal_walls[high / width] & (0b1 << (high % width)) != 0
+    } else if high - low == 1 && !high.is_multiple_of(width) {
+        vertical_walls[high % width] & (0b1 << (high / width)) != 0
+    } else {
+        true
+    }
+}
+
+/// Pushes a circle of `radius` centred on (`x`, `y`) back out of any wall or wall end it
+/// overlaps. Applied after every step, this makes movement into a wall slide along it.
+/// The circle must already have been clear of the walls before the step.
+pub fn push_out_of_walls<const H: usize, const W: usize>(
+    x: f32,
+    y: f32,
+    radius: f32,
+    horizontal_walls: &Vec<u32,H>,
+    vertical_walls: &Vec<u32,W>
+) -> (f32, f32) {
+    let (column, row) = (floorf(x) as i32, floorf(y) as i32);
+    let (mut x, mut y) = (x, y);
+
+    // The sides of the cell the centre is in
+    if wall_at(column, row, vertical_walls) {
+        x = x.max(column as f32 + radius);
+    }
+    if wall_at(column + 1, row, vertical_walls) {
+        x = x.min((column + 1) as f32 - radius);
+    }
+    if wall_at(row, column, horizontal_walls) {
+        y = y.max(row as f32 + radius);
+    }
+    if wall_at(row + 1, column, horizontal_walls) {
+        y = y.min((row + 1) as f32 - radius);
+    }
+
+    // The ends of walls belonging to neighboring cells stick out at the corners
+    for (corner_x, corner_y) in [(column, row), (column + 1, row), (column, row + 1), (column + 1, row + 1)] {
+        let post = wall_at(corner_y, corner_x - 1, horizontal_walls) || wall_at(corner_y, corner_x, horizontal_walls) ||
+            wall_at(corner_x, corner_y - 1, vertical_walls) || wall_at(corner_x, corner_y, vertical_walls);
+        let (dx, dy) = (x - corner_x as f32, y - corner_y as f32);
+        let separation = sqrtf(dx * dx + dy * dy);
+        if post && separation < radius && separation > 0.0 {
+            x = corner_x as f32 + dx * radius / separation;
+            y = corner_y as f32 + dy * radius / separation;
+        }
+    }
+    (x, y)
+}
+
+/// Whether wall line `line` has a wall alongside cell `cell` (see `find_walls`).
+fn wall_at<const N: usize>(line: i32, cell: i32, walls: &Vec<u32,N>) -> bool {
+    if line < 0 || !(0..32).contains(&cell) {
+        return false;
+    }
+    walls.get(line as usize).is_some_and(|bits| bits & (0b1 << cell) != 0)
+}
@@ -0,0 +1,81 @@
+use crate::wasm4::{
+    DRAW_COLORS,
+    BUTTON_UP, BUTTON_DOWN,
+    BUTTON_LEFT, BUTTON_RIGHT,
+    BUTTON_1,
+    rect, text
+};
+
+// Height of each menu row in pixels (the system font is 8 pixels tall)
+const ROW_HEIGHT: i32 = 12;
+
+/// What the player did to a menu this frame.
+#[derive(Clone, Copy, PartialEq)]
+pub enum MenuEvent {
+    Nothing,
+    /// Left (-1) or right (+1) was pressed on a row.
+    Change(usize, i32),
+    /// Button 1 was pressed on a row.
+    Select(usize)
+}
+
+/// A vertical list of text rows with a cursor.
+pub struct Menu {
+    pub cursor: usize
+}
+
+impl Menu {
+
+    pub const fn new() -> Menu {
+        Menu { cursor: 0 }
+    }
+
+    /// Up and down move the cursor (wrapping around), left and right change the row
+    /// under the cursor and button 1 selects it. `pressed` holds the newly pressed buttons.
+    pub fn navigate(&mut self, pressed: u8, rows: usize) -> MenuEvent {
+        if pressed & BUTTON_UP != 0 {
+            self.cursor = (self.cursor + rows - 1) % rows;
+        }
+        if pressed & BUTTON_DOWN != 0 {
+            self.cursor = (self.cursor + 1) % rows;
+        }
+
+        if pressed & BUTTON_1 != 0 {
+            MenuEvent::Select(self.cursor)
+        } else if pressed & BUTTON_LEFT != 0 {
+            MenuEvent::Change(self.cursor, -1)
+        } else if pressed & BUTTON_RIGHT != 0 {
+            MenuEvent::Change(self.cursor, 1)
+        } else {
+            MenuEvent::Nothing
+        }
+    }
+
+    /// Draws the rows starting at (x, y), with the row under the cursor highlighted.
+    pub fn draw(&self, rows: &[&str], x: i32, y: i32) {
+        for (index, row) in rows.iter().enumerate() {
+            let top = y + ROW_HEIGHT * index as i32;
+            if index == self.cursor {
+                unsafe { *DRAW_COLORS = 0x33 };
+                rect(x - 2, top - 2, (row.len() * 8 + 4) as u32, ROW_HEIGHT as u32);
+                unsafe { *DRAW_COLORS = 0x01 };
+            } else {
+                unsafe { *DRAW_COLORS = 0x04 };
+            }
+            text(row, x, top);
+        }
+    }
+}
+
+impl Default for Menu {
+    fn default() -> Menu {
+        Menu::new()
+    }
+}
+
+/// Steps through a list of choices (wrapping around) from the current one.
+pub fn cycle<T: Copy + PartialEq>(choices: &[T], current: T, step: i32) -> T {
+    let index = choices.iter().position(|&c| c == current).unwrap_or(0) as i32;
+    let count = choices.len() as i32;
+    choices[(index + step).rem_euclid(count) as usize]
+}
@@ -0,0 +1,52 @@
+use crate::constants::PICKUP_RESPAWN;
+
+/// What a pickup does for the player who walks over it.
+#[derive(Clone, Copy, PartialEq)]
+pub enum PickupKind {
+    /// Restores some life.
+    Health,
+    /// Loads every spent round at once.
+    Reload,
+    /// A spare magazine, swapped in when the current one runs dry.
+    Magazine
+}
+
+impl PickupKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            PickupKind::Health => "Health",
+            PickupKind::Reload => "Reload",
+            PickupKind::Magazine => "Magazine"
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub struct Pickup {
+    pub x: f32,
+    pub y: f32,
+    pub kind: PickupKind,
+    /// Frames until the pickup comes back after being taken (0 while it is there).
+    pub respawn: u32
+}
+
+impl Pickup {
+
+    pub fn new(x: f32, y: f32, kind: PickupKind) -> Pickup {
+        Pickup {
+            x,
+            y,
+            kind,
+            respawn: 0
+        }
+    }
+
+    pub fn available(&self) -> bool {
+        self.respawn == 0
+    }
+
+    /// Takes the pickup away until its respawn timer runs out.
+    pub fn take(&mut self) {
+        self.respawn = PICKUP_RESPAWN;
+    }
+}
\ No newline at end of file
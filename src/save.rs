@@ -0,0 +1,140 @@
+use heapless::Vec;
+
+use crate::state::{ControlScheme, GameMode, MazeSize};
+
+/// Bump this whenever the layout below changes; older saves are then ignored.
+pub const SAVE_VERSION: u8 = 1;
+pub const MAX_HIGH_SCORES: usize = 10;
+
+// Layout of the save file, all numbers little-endian:
+//   0..4   seed, rewritten by the web page on every load
+//   4      version
+//   5      number of high scores
+//   6..8   number of rounds played
+//   8..    high scores, best first, HIGH_SCORE_SIZE bytes each
+//   88     control scheme (older saves end before this, which reads as the default)
+const HEADER_SIZE: usize = 8;
+const HIGH_SCORE_SIZE: usize = 8;
+const CONTROLS_OFFSET: usize = HEADER_SIZE + MAX_HIGH_SCORES * HIGH_SCORE_SIZE;
+pub const SAVE_SIZE: usize = CONTROLS_OFFSET + 1;
+
+/// One line of the high-score table.
+#[derive(Clone, Copy, PartialEq)]
+pub struct HighScore {
+    pub score: i32,
+    pub mode: GameMode,
+    pub size: MazeSize,
+    /// Which round this was, counting every round ever played.
+    pub run: u16
+}
+
+/// Everything kept on disk between sessions.
+pub struct SaveData {
+    pub seed: u32,
+    pub runs: u16,
+    pub high_scores: Vec<HighScore, MAX_HIGH_SCORES>,
+    pub controls: ControlScheme
+}
+
+impl SaveData {
+
+    pub const fn new() -> SaveData {
+        SaveData {
+            seed: 0,
+            runs: 0,
+            high_scores: Vec::<HighScore, MAX_HIGH_SCORES>::new(),
+            controls: ControlScheme::Classic
+        }
+    }
+
+    /// Reads a save file. Anything but the seed is discarded if the version doesn't match.
+    pub fn from_bytes(bytes: &[u8; SAVE_SIZE]) -> SaveData {
+        let mut save = SaveData::new();
+        save.seed = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        if bytes[4] != SAVE_VERSION {
+            return save;
+        }
+
+        save.runs = u16::from_le_bytes([bytes[6], bytes[7]]);
+        save.controls = match bytes[CONTROLS_OFFSET] {
+            1 => ControlScheme::Strafe,
+            2 => ControlScheme::Mouse,
+            _ => ControlScheme::Classic
+        };
+        let count = (bytes[5] as usize).min(MAX_HIGH_SCORES);
+        for entry in bytes[HEADER_SIZE..].chunks_exact(HIGH_SCORE_SIZE).take(count) {
+            let mode = match entry[4] {
+                0 => GameMode::Deathmatch,
+                1 => GameMode::Race,
+                2 => GameMode::FreeForAll,
+                _ => continue
+            };
+            let size = match entry[5] {
+                0 => MazeSize::Small,
+                1 => MazeSize::Medium,
+                2 => MazeSize::Large,
+                _ => continue
+            };
+            save.high_scores.push(HighScore {
+                score: i32::from_le_bytes([entry[0], entry[1], entry[2], entry[3]]),
+                mode,
+                size,
+                run: u16::from_le_bytes([entry[6], entry[7]])
+            }).ok();
+        }
+        save
+    }
+
+    /// Writes the save file in the current version's layout.
+    pub fn to_bytes(&self) -> [u8; SAVE_SIZE] {
+        let mut bytes = [0u8; SAVE_SIZE];
+        bytes[0..4].copy_from_slice(&self.seed.to_le_bytes());
+        bytes[4] = SAVE_VERSION;
+        bytes[5] = self.high_scores.len() as u8;
+        bytes[6..8].copy_from_slice(&self.runs.to_le_bytes());
+        for (high_score, entry) in self.high_scores.iter().zip(bytes[HEADER_SIZE..].chunks_exact_mut(HIGH_SCORE_SIZE)) {
+            entry[0..4].copy_from_slice(&high_score.score.to_le_bytes());
+            entry[4] = match high_score.mode {
+                GameMode::Deathmatch => 0,
+                GameMode::Race => 1,
+                GameMode::FreeForAll => 2
+            };
+            entry[5] = match high_score.size {
+                MazeSize::Small => 0,
+                MazeSize::Medium => 1,
+                MazeSize::Large => 2
+            };
+            entry[6..8].copy_from_slice(&high_score.run.to_le_bytes());
+        }
+        bytes[CONTROLS_OFFSET] = match self.controls {
+            ControlScheme::Classic => 0,
+            ControlScheme::Strafe => 1,
+            ControlScheme::Mouse => 2
+        };
+        bytes
+    }
+
+    /// Counts a finished round and adds its score to the table if it is good enough.
+    /// Returns the score's position in the table, if it made it.
+    pub fn record(&mut self, score: i32, mode: GameMode, size: MazeSize) -> Option<usize> {
+        self.runs = self.runs.wrapping_add(1);
+        let high_score = HighScore { score, mode, size, run: self.runs };
+
+        // Ties go to the earlier round
+        let rank = self.high_scores.iter().position(|h| h.score < score).unwrap_or(self.high_scores.len());
+        if rank >= MAX_HIGH_SCORES {
+            return None;
+        }
+        if self.high_scores.is_full() {
+            self.high_scores.pop();
+        }
+        self.high_scores.insert(rank, high_score).ok();
+        Some(rank)
+    }
+}
+
+impl Default for SaveData {
+    fn default() -> SaveData {
+        SaveData::new()
+    }
+}
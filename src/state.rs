@@ -1,27 +1,44 @@
-use libm::{atan2f, cosf, fabsf, floorf, sinf};
-use core::{f32::consts::PI, fmt::Write};
+use libm::{cosf, fabsf, floorf, sinf};
+use core::f32::consts::PI;
 
 use rand::{SeedableRng, Rng};
 use rand::rngs::SmallRng;
-use rand::seq::SliceRandom;
 
-use heapless::{String, Vec};
+use heapless::Vec;
 
 use crate::constants::{
-    WIDTH, HEIGHT, NUM_CELLS, MAX_PASSAGES, STEP_SIZE, BULLET_SPEED, 
-    RELOAD_TIME, NUM_BULLETS, NUM_PLAYERS, BULLETS_PER_PLAYER, PLAYER_WIDTH
+    MAX_WIDTH, MAX_HEIGHT, MAX_CELLS, MAX_PASSAGES, MAX_EXITS, STEP_SIZE, HALF_FOV,
+    NUM_BULLETS, NUM_PLAYERS, BULLETS_PER_PLAYER, PLAYER_WIDTH, PLAYER_RADIUS, MAX_BOUNCES,
+    MAX_LIFE, NUM_PICKUPS, MAX_SPARE_MAGAZINES
 };
 
 use crate::wasm4::{
     tone,
-    TONE_NOISE,
-    trace
+    TONE_NOISE, TONE_PULSE1,
+    BUTTON_UP, BUTTON_DOWN,
+    BUTTON_LEFT, BUTTON_RIGHT,
+    BUTTON_1, BUTTON_2
 };
 
-use maze_gen::{find_passages, find_walls, there_is_no_passage_here};
-use crate::arms::{Ammo, Bullet};
+use crate::maze::{
+    Algorithm, Passage, UNREACHABLE,
+    braid, distance_field, find_walls, generate_passages, open_neighbors, push_out_of_walls, there_is_a_wall_between
+};
+use crate::arms::{Ammo, Bullet, Weapon};
+use crate::pickups::{Pickup, PickupKind};
+
+use crate::util::{angle_difference, distance, get_center_from_index, get_index, point_in_wall, sweep_circle};
+use crate::view::line_of_sight;
+
+// Independent random streams drawn by each player every frame
+const AIM_STREAM: u64 = 0;
+const PATH_STREAM: u64 = 1;
+const RNG_STREAMS: u64 = 2;
+
+// Bots with this much life or less run off and hide
+const FLEE_LIFE: i32 = 2;
 
-use crate::util::{distance, get_center_from_index, get_index};
+const EMPTY_FIELD: Vec<u16,MAX_CELLS> = Vec::new();
 
 #[derive(Clone, Copy)]
 pub enum View {
@@ -29,22 +46,253 @@ pub enum View {
     TopDown
 }
 
+/// What it takes to win a round.
+#[derive(Clone, Copy, PartialEq)]
+pub enum GameMode {
+    /// Last player standing.
+    Deathmatch,
+    /// First player to reach the exit in the middle of the maze wins.
+    Race,
+    /// Last player standing, but the bots fight each other as well as player 1.
+    /// Whatever the mode, player 1's score ticks down until their round is over.
+    FreeForAll
+}
+
+impl GameMode {
+    pub fn name(self) -> &'static str {
+        match self {
+            GameMode::Deathmatch => "Deathmatch",
+            GameMode::Race => "Race",
+            GameMode::FreeForAll => "Free-for-all"
+        }
+    }
+}
+
+/// How many cells across and down the maze is.
+#[derive(Clone, Copy, PartialEq)]
+pub enum MazeSize {
+    Small,
+    Medium,
+    Large
+}
+
+impl MazeSize {
+    /// Width and height of the maze in cells.
+    pub fn dimensions(self) -> (usize, usize) {
+        match self {
+            MazeSize::Small => (9, 9),
+            MazeSize::Medium => (13, 13),
+            MazeSize::Large => (MAX_WIDTH, MAX_HEIGHT)
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            MazeSize::Small => "Small",
+            MazeSize::Medium => "Medium",
+            MazeSize::Large => "Large"
+        }
+    }
+}
+
+/// How well the bots play.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Difficulty {
+    Easy,
+    Normal,
+    Hard,
+    Nightmare
+}
+
+/// The knobs a difficulty turns on every bot.
+#[derive(Clone, Copy, PartialEq)]
+pub struct BotSkill {
+    /// Distance moved per frame.
+    pub speed: f32,
+    /// Radians turned per frame.
+    pub turn_rate: f32,
+    /// Frames a target must be in range before the bot pulls the trigger.
+    pub reaction: u8,
+    /// Width of the random spread added to each shot, in radians.
+    pub aim_error: f32,
+    /// How close the target must be to fire at it.
+    pub fire_range: f32,
+    /// How many bullets the bot can have loaded at once.
+    pub magazine: usize
+}
+
+impl Difficulty {
+    pub fn skill(self) -> BotSkill {
+        match self {
+            Difficulty::Easy => BotSkill {
+                speed: STEP_SIZE * 0.5, turn_rate: STEP_SIZE * 0.5, reaction: 30,
+                aim_error: 0.4, fire_range: 2.0, magazine: 1
+            },
+            Difficulty::Normal => BotSkill {
+                speed: STEP_SIZE * 0.65, turn_rate: STEP_SIZE * 0.65, reaction: 15,
+                aim_error: 0.1, fire_range: 3.0, magazine: 1
+            },
+            Difficulty::Hard => BotSkill {
+                speed: STEP_SIZE * 0.85, turn_rate: STEP_SIZE, reaction: 8,
+                aim_error: 0.06, fire_range: 4.0, magazine: 2
+            },
+            Difficulty::Nightmare => BotSkill {
+                speed: STEP_SIZE, turn_rate: STEP_SIZE * 1.5, reaction: 0,
+                aim_error: 0.02, fire_range: 6.0, magazine: 3
+            }
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Difficulty::Easy => "Easy",
+            Difficulty::Normal => "Normal",
+            Difficulty::Hard => "Hard",
+            Difficulty::Nightmare => "Nightmare"
+        }
+    }
+}
+
+/// How the buttons (and the mouse) move a player.
+#[derive(Clone, Copy, PartialEq)]
+pub enum ControlScheme {
+    /// Left and right turn; button 2 switches view.
+    Classic,
+    /// Holding button 2 makes left and right sidestep; tapping it switches view.
+    Strafe,
+    /// Left and right always sidestep and player 1 turns with the mouse; button 2 switches view.
+    /// Other players, who have no mouse, get the strafe controls.
+    Mouse
+}
+
+impl ControlScheme {
+    pub fn name(self) -> &'static str {
+        match self {
+            ControlScheme::Classic => "Classic",
+            ControlScheme::Strafe => "Strafe",
+            ControlScheme::Mouse => "Mouse"
+        }
+    }
+}
+
+/// How a bot picks who to fight in a free-for-all.
+#[derive(Clone, Copy, PartialEq)]
+enum Targeting {
+    Nearest,
+    Weakest,
+    /// Goes after whoever last shot it, or else the nearest.
+    LastAttacker
+}
+
+// Each bot slot has its own temperament (player 1's entry only matters if a bot ever plays it)
+const TARGETING: [Targeting; NUM_PLAYERS] = [
+    Targeting::Nearest, Targeting::Nearest, Targeting::Weakest, Targeting::LastAttacker
+];
+
+/// Everything that is chosen before a round starts.
+#[derive(Clone, Copy, PartialEq)]
+pub struct Options {
+    pub mode: GameMode,
+    pub size: MazeSize,
+    pub algorithm: Algorithm,
+    /// Fraction of dead ends to open up into loops.
+    pub braid: f32,
+    pub difficulty: Difficulty,
+    pub controls: ControlScheme,
+    /// Whether bullets bounce off walls.
+    pub ricochet: bool,
+    /// Who drives each slot.
+    pub players: [Controller; NUM_PLAYERS],
+    /// Whether pressing a button on a bot's gamepad hands that slot to a person.
+    pub drop_in: bool
+}
+
+impl Options {
+
+    pub const fn new() -> Options {
+        Options {
+            mode: GameMode::Deathmatch,
+            size: MazeSize::Medium,
+            algorithm: Algorithm::RecursiveBacktracker,
+            // Open up a quarter of the dead ends so there is room to flank
+            braid: 0.25,
+            difficulty: Difficulty::Normal,
+            controls: ControlScheme::Classic,
+            ricochet: false,
+            players: [Controller::Human, Controller::Bot, Controller::Bot, Controller::Bot],
+            drop_in: false
+        }
+    }
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options::new()
+    }
+}
+
+/// Who is driving a player slot.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Controller {
+    Human,
+    Bot
+}
+
+impl Controller {
+    pub fn name(self) -> &'static str {
+        match self {
+            Controller::Human => "Human",
+            Controller::Bot => "Bot"
+        }
+    }
+}
+
 pub struct State {
     pub player_x: [f32; NUM_PLAYERS],
     pub player_y: [f32; NUM_PLAYERS],
     pub player_angle: [f32; NUM_PLAYERS],
     pub player_ammo: [[Ammo;BULLETS_PER_PLAYER]; NUM_PLAYERS],
+    pub player_weapon: [Weapon; NUM_PLAYERS],
+    pub spare_magazines: [u8; NUM_PLAYERS],
     pub player_life: [i32; NUM_PLAYERS],
     pub player_view: [View; NUM_PLAYERS],
+    pub player_controller: [Controller; NUM_PLAYERS],
+    pub drop_in: bool,
+    previous_gamepads: [u8; NUM_PLAYERS],
+    pub controls: ControlScheme,
+    // Whether anything else was pressed while button 2 was held, in which case letting go
+    // of it does not switch view
+    button_2_used: [bool; NUM_PLAYERS],
+    pub difficulty: Difficulty,
+    // Frames each bot has had its target in range
+    reaction_timers: [u8; NUM_PLAYERS],
     pub bullets: Vec<Bullet,NUM_BULLETS>,
-    visited: Vec<bool,NUM_CELLS>,
-    passages: Vec<(usize,usize),MAX_PASSAGES>,
-    pub horizontal_walls: Vec<u16,{HEIGHT+1}>,
-    pub vertical_walls: Vec<u16,{WIDTH+1}>,
-    paths: Vec::<usize, MAX_PASSAGES>,
-    pruned_path: Vec::<usize, MAX_PASSAGES>,
-    stack: Vec::<usize, MAX_PASSAGES>,
+    pub ricochet: bool,
+    pub pickups: Vec<Pickup,NUM_PICKUPS>,
+    visited: Vec<bool,MAX_CELLS>,
+    passages: Vec<Passage,MAX_PASSAGES>,
+    pub size: MazeSize,
+    pub width: usize,
+    pub height: usize,
+    pub horizontal_walls: Vec<u32,{MAX_HEIGHT+1}>,
+    pub vertical_walls: Vec<u32,{MAX_WIDTH+1}>,
+    pub mode: GameMode,
+    pub exits: Vec<usize,MAX_EXITS>,
+    pub finish_time: [Option<u32>; NUM_PLAYERS],
+    pub winner: Option<usize>,
+    pub kills: [u32; NUM_PLAYERS],
+    last_attacker: [Option<usize>; NUM_PLAYERS],
+    // Distance fields toward the cells bots are heading for this frame, one per goal
+    field_goals: Vec<Goal,NUM_PLAYERS>,
+    fields: [Vec<u16,MAX_CELLS>; NUM_PLAYERS],
+    // Who each bot is after, where it last saw them, and where it is wandering to otherwise
+    targets: [Option<usize>; NUM_PLAYERS],
+    last_seen: [Option<usize>; NUM_PLAYERS],
+    wander_goals: [Option<usize>; NUM_PLAYERS],
+    pub player_brain: [Brain; NUM_PLAYERS],
+    hideouts: [Option<usize>; NUM_PLAYERS],
     seed: u64,
+    frame: u32,
     pub score: i32,
     accumulator: u8
 }
@@ -57,93 +305,290 @@ impl State {
             player_y: [0.5, 0.5, 12.5, 12.5],
             player_angle: [0.75, 2.25, 3.75, 5.25],
             player_ammo: [[Ammo::Loaded; BULLETS_PER_PLAYER]; NUM_PLAYERS],
-            player_life: [5; NUM_PLAYERS],
+            player_weapon: [Weapon::Pistol; NUM_PLAYERS],
+            spare_magazines: [0; NUM_PLAYERS],
+            player_life: [MAX_LIFE; NUM_PLAYERS],
             player_view: [View::FirstPerson; NUM_PLAYERS],
+            player_controller: [Controller::Human, Controller::Bot, Controller::Bot, Controller::Bot],
+            drop_in: false,
+            previous_gamepads: [0; NUM_PLAYERS],
+            controls: ControlScheme::Classic,
+            button_2_used: [false; NUM_PLAYERS],
+            difficulty: Difficulty::Normal,
+            reaction_timers: [0; NUM_PLAYERS],
             bullets: Vec::<Bullet,NUM_BULLETS>::new(),
-            visited: Vec::<bool,NUM_CELLS>::new(),
-            passages: Vec::<(usize,usize),MAX_PASSAGES>::new(),
-            horizontal_walls: Vec::<u16,{HEIGHT+1}>::new(),
-            vertical_walls: Vec::<u16,{WIDTH+1}>::new(),
-            paths: Vec::<usize, MAX_PASSAGES>::new(),
-            pruned_path: Vec::<usize, MAX_PASSAGES>::new(),
-            stack: Vec::<usize, MAX_PASSAGES>::new(),
+            ricochet: false,
+            pickups: Vec::<Pickup,NUM_PICKUPS>::new(),
+            visited: Vec::<bool,MAX_CELLS>::new(),
+            passages: Vec::<Passage,MAX_PASSAGES>::new(),
+            size: MazeSize::Medium,
+            width: 0,
+            height: 0,
+            horizontal_walls: Vec::<u32,{MAX_HEIGHT+1}>::new(),
+            vertical_walls: Vec::<u32,{MAX_WIDTH+1}>::new(),
+            mode: GameMode::Deathmatch,
+            exits: Vec::<usize,MAX_EXITS>::new(),
+            finish_time: [None; NUM_PLAYERS],
+            winner: None,
+            kills: [0; NUM_PLAYERS],
+            last_attacker: [None; NUM_PLAYERS],
+            field_goals: Vec::<Goal,NUM_PLAYERS>::new(),
+            fields: [EMPTY_FIELD; NUM_PLAYERS],
+            targets: [None; NUM_PLAYERS],
+            last_seen: [None; NUM_PLAYERS],
+            wander_goals: [None; NUM_PLAYERS],
+            player_brain: [Brain::Wander; NUM_PLAYERS],
+            hideouts: [None; NUM_PLAYERS],
             seed: 0,
+            frame: 0,
             score: 200,
             accumulator: 0
         }
     }
 
-    /// Called from the game start(), this creates a random maze.
-    pub fn generate_maze(&mut self, rng: &mut SmallRng) {
+    /// Starts a new round: every player is back in their corner with full life and ammo,
+    /// and a fresh maze is generated from `seed`. Who controls each slot comes from `options`;
+    /// the weapon each carries is kept.
+    pub fn reset(&mut self, seed: u64, options: Options) {
+        self.player_ammo = [[Ammo::Loaded; BULLETS_PER_PLAYER]; NUM_PLAYERS];
+        self.spare_magazines = [0; NUM_PLAYERS];
+        self.player_life = [MAX_LIFE; NUM_PLAYERS];
+        self.player_view = [View::FirstPerson; NUM_PLAYERS];
+        self.bullets.clear();
+        self.mode = options.mode;
+        self.difficulty = options.difficulty;
+        self.controls = options.controls;
+        self.ricochet = options.ricochet;
+        self.player_controller = options.players;
+        self.drop_in = options.drop_in;
+        self.button_2_used = [false; NUM_PLAYERS];
+        self.reaction_timers = [0; NUM_PLAYERS];
+        self.finish_time = [None; NUM_PLAYERS];
+        self.winner = None;
+        self.kills = [0; NUM_PLAYERS];
+        self.last_attacker = [None; NUM_PLAYERS];
+        self.score = 200;
+        self.accumulator = 0;
+
+        let mut rng = SmallRng::seed_from_u64(seed);
+        self.generate_maze(&mut rng, options.size, options.algorithm, options.braid);
+    }
+
+    /// Creates a random maze of the given size, replacing any previous one,
+    /// and puts a player in each of its corners. `braid_factor` is the fraction of
+    /// dead ends to open up into loops.
+    pub fn generate_maze(&mut self, rng: &mut SmallRng, size: MazeSize, algorithm: Algorithm, braid_factor: f32) {
+        let (width, height) = size.dimensions();
+        self.size = size;
+        self.width = width;
+        self.height = height;
+
         // Initialize an empty maze
-        self.visited.extend_from_slice(&[false;NUM_CELLS]).unwrap();
-        self.horizontal_walls.extend_from_slice(&[0b0000000000000000;{HEIGHT+1}]).unwrap();
-        self.vertical_walls.extend_from_slice(&[0b0000000000000000;{WIDTH+1}]).unwrap();
+        self.visited.clear();
+        self.visited.resize(width * height, false).unwrap();
+        self.passages.clear();
+        self.exits.clear();
 
-        // Randomly create passages to define the maze, starting from first index
-        let index = 0;
-        find_passages(index, WIDTH, HEIGHT, &mut self.visited, &mut self.passages, rng);
+        // Randomly create passages to define the maze, then add loops
+        generate_passages(algorithm, width, height, &mut self.visited, &mut self.passages, rng);
+        braid(width, height, braid_factor, &mut self.passages, rng);
 
         // Use the passages to define the walls of the maze
-        find_walls(WIDTH, HEIGHT, &mut self.passages, &mut self.horizontal_walls, &mut self.vertical_walls);
+        find_walls(width, height, &self.passages, &mut self.horizontal_walls, &mut self.vertical_walls);
 
-        self.seed = rng.gen::<u64>();
-    }
+        // Spawn in the corners
+        let (right, bottom) = (width as f32 - 0.5, height as f32 - 0.5);
+        self.player_x = [0.5, right, 0.5, right];
+        self.player_y = [0.5, 0.5, bottom, bottom];
+        self.player_angle = [0.75, 2.25, 3.75, 5.25];
+        self.targets = [None; NUM_PLAYERS];
+        self.last_seen = [None; NUM_PLAYERS];
+        self.wander_goals = [None; NUM_PLAYERS];
+        self.player_brain = [Brain::Wander; NUM_PLAYERS];
+        self.hideouts = [None; NUM_PLAYERS];
 
-    /// Update the game state based on user input.
-    pub fn update(
-        &mut self, 
-        p1_up: bool, p1_down: bool, p1_left: bool, p1_right: bool, p1_shoot: bool, p1_toggle_view: bool
-    ) {
-        // AI Update for Players 2, 3, 4
-        let (p2_up, p2_down, p2_left, p2_right, p2_shoot, p2_toggle_view) = self.update_enemy(2);
-        let (p3_up, p3_down, p3_left, p3_right, p3_shoot, p3_toggle_view) = self.update_enemy(3);
-        let (p4_up, p4_down, p4_left, p4_right, p4_shoot, p4_toggle_view) = self.update_enemy(4);
-
-        // Player 1
-        if self.player_life[0] > 0 {
-            self.update_player(0, p1_up, p1_down, p1_left, p1_right);
-            self.update_ammo(0, p1_shoot);
-            self.update_view(0, p1_toggle_view);
-        } else {
-            self.player_view[0] = View::FirstPerson;
-            self.accumulator = 0;
+        // The exit sits in the middle of the maze, the same distance from every spawn corner
+        let columns: &[usize] = if width % 2 == 1 { &[width / 2] } else { &[width / 2 - 1, width / 2] };
+        let rows: &[usize] = if height % 2 == 1 { &[height / 2] } else { &[height / 2 - 1, height / 2] };
+        for row in rows {
+            for column in columns {
+                self.exits.push(column + row * width).ok();
+            }
         }
-        // Player 2
-        if self.player_life[1] > 0 {
-            self.update_player(1, p2_up, p2_down, p2_left, p2_right);
-            self.update_ammo(1, p2_shoot);
-            self.update_view(1, p2_toggle_view);
+
+        self.seed = rng.gen::<u64>();
+        self.frame = 0;
+
+        // Scatter pickups around the maze, away from the spawn corners and the exit
+        let corners = [0, width - 1, width * (height - 1), width * height - 1];
+        let mut cells = Vec::<usize,NUM_PICKUPS>::new();
+        while !cells.is_full() {
+            let cell = rng.gen_range(0..width * height);
+            if !corners.contains(&cell) && !self.exits.contains(&cell) && !cells.contains(&cell) {
+                cells.push(cell).ok();
+            }
         }
-        // Player 3
-        if self.player_life[2] > 0 {
-            self.update_player(2, p3_up, p3_down, p3_left, p3_right);
-            self.update_ammo(2, p3_shoot);
-            self.update_view(2, p3_toggle_view);
+        const KINDS: [PickupKind; 3] = [PickupKind::Health, PickupKind::Reload, PickupKind::Magazine];
+        self.pickups = cells.iter().enumerate().map(|(index, &cell)| {
+            let (x, y) = get_center_from_index(cell, width, height);
+            Pickup::new(x, y, KINDS[index % KINDS.len()])
+        }).collect();
+    }
+
+    /// Number of frames since the maze was generated.
+    pub fn elapsed(&self) -> u32 {
+        self.frame
+    }
+
+    /// Who the bot in slot `pidx` is after, if anyone.
+    pub fn target(&self, pidx: usize) -> Option<usize> {
+        self.targets[pidx]
+    }
+
+    /// Returns a random number generator for one player on the current frame.
+    /// Every draw is derived from the maze seed, the frame and the player slot (never from
+    /// earlier draws), so netplay peers re-simulating the same frame get the same numbers.
+    fn player_rng(&self, pidx: usize, stream: u64) -> SmallRng {
+        let key = (self.frame as u64 * NUM_PLAYERS as u64 + pidx as u64) * RNG_STREAMS + stream;
+        SmallRng::seed_from_u64(self.seed.wrapping_add(key))
+    }
+
+    /// Update the game state based on the four gamepads.
+    /// Slots controlled by a bot ignore their gamepad; with drop-in on, pressing a button
+    /// on it hands that slot to a person.
+    pub fn update(&mut self, gamepads: [u8; NUM_PLAYERS]) {
+        self.field_goals.clear();
+
+        // Gather everyone's input before anyone moves
+        let mut inputs = [(false,false,false,false,false,false,false); NUM_PLAYERS];
+        for pidx in 0..NUM_PLAYERS {
+            let pressed = gamepads[pidx] & (gamepads[pidx] ^ self.previous_gamepads[pidx]);
+            if self.drop_in && self.player_controller[pidx] == Controller::Bot && pressed != 0 {
+                self.player_controller[pidx] = Controller::Human;
+            }
+            inputs[pidx] = match self.player_controller[pidx] {
+                Controller::Human => self.read_gamepad(pidx, gamepads[pidx]),
+                Controller::Bot => self.update_enemy(pidx + 1)
+            };
         }
-        // Player 4
-        if self.player_life[3] > 0 {
-            self.update_player(3, p4_up, p4_down, p4_left, p4_right);
-            self.update_ammo(3, p4_shoot);
-            self.update_view(3, p4_toggle_view);
+        self.previous_gamepads = gamepads;
+
+        for (pidx, (up, down, left, right, shoot, toggle_view, strafe)) in inputs.into_iter().enumerate() {
+            if self.finish_time[pidx].is_some() {
+                continue;
+            }
+            if self.player_life[pidx] > 0 {
+                self.update_player(pidx, up, down, left, right, strafe);
+                self.update_ammo(pidx, shoot);
+                self.update_view(pidx, toggle_view);
+            } else {
+                self.player_view[pidx] = View::FirstPerson;
+                if pidx == 0 {
+                    self.accumulator = 0;
+                }
+            }
         }
         // Bullets in flight
         self.update_bullets();
+        self.update_pickups();
+
+        match self.mode {
+            GameMode::Race => self.update_race(),
+            GameMode::FreeForAll => self.update_free_for_all(),
+            GameMode::Deathmatch => {}
+        }
 
         self.accumulator += 1;
         if self.accumulator == 59 {
             self.accumulator = 0;
-            if self.player_life[0] > 0 && (
-                self.player_life[1] > 0 ||
-                self.player_life[2] > 0 ||
-                self.player_life[3] > 0 
-            ) {
+            if !self.round_over() {
                 self.score -= 1;
             }
-            // let temp = self.score;
-            // let mut data = String::<64>::new();
-            // write!(data, "{temp}").unwrap();
-            // trace(data);
+        }
+
+        self.frame = self.frame.wrapping_add(1);
+    }
+
+    /// Whether player 1's round is finished, which stops their score from changing.
+    /// A deathmatch ends when player 1 dies or is the last one standing;
+    /// a race ends when player 1 dies or reaches the exit.
+    pub fn round_over(&self) -> bool {
+        match self.mode {
+            GameMode::Deathmatch | GameMode::FreeForAll =>
+                self.player_life[0] <= 0 || self.player_life[1..].iter().all(|&life| life <= 0),
+            GameMode::Race => self.player_life[0] <= 0 || self.finish_time[0].is_some()
+        }
+    }
+
+    /// Records when each living player first reaches an exit; the first to get there wins.
+    fn update_race(&mut self) {
+        for pidx in 0..NUM_PLAYERS {
+            if self.player_life[pidx] > 0 && self.finish_time[pidx].is_none() {
+                let index = get_index(self.player_x[pidx], self.player_y[pidx], self.width, self.height);
+                if self.exits.contains(&index) {
+                    self.finish_time[pidx] = Some(self.frame);
+                    if self.winner.is_none() {
+                        self.winner = Some(pidx);
+                        // Beating everyone to the exit is worth as much as a kill
+                        if pidx == 0 {
+                            self.score += 60;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Declares the last player standing the winner.
+    fn update_free_for_all(&mut self) {
+        let mut alive = (0..NUM_PLAYERS).filter(|&pidx| self.player_life[pidx] > 0);
+        if let (Some(pidx), None) = (alive.next(), alive.next()) {
+            self.winner.get_or_insert(pidx);
+        }
+    }
+
+    /// Decodes a gamepad into movement buttons (held) and shoot/toggle buttons (newly pressed).
+    /// The last element says whether left and right sidestep rather than turn.
+    fn read_gamepad(&mut self, pidx: usize, gamepad: u8) -> (bool,bool,bool,bool,bool,bool,bool) {
+        let pressed = gamepad & (gamepad ^ self.previous_gamepads[pidx]);
+        let released = self.previous_gamepads[pidx] & !gamepad;
+        let scheme = match self.controls {
+            ControlScheme::Mouse if pidx != 0 => ControlScheme::Strafe,
+            scheme => scheme
+        };
+
+        let (toggle_view, strafe) = match scheme {
+            ControlScheme::Classic => (pressed & BUTTON_2 != 0, false),
+            ControlScheme::Mouse => (pressed & BUTTON_2 != 0, true),
+            ControlScheme::Strafe => {
+                // Button 2 only switches view when it is tapped on its own
+                let holding = gamepad & BUTTON_2 != 0;
+                if holding && gamepad & (BUTTON_LEFT | BUTTON_RIGHT | BUTTON_1) != 0 {
+                    self.button_2_used[pidx] = true;
+                }
+                let tapped = released & BUTTON_2 != 0 && !self.button_2_used[pidx];
+                if !holding {
+                    self.button_2_used[pidx] = false;
+                }
+                (tapped, holding)
+            }
+        };
+
+        (
+            gamepad & BUTTON_UP != 0,
+            gamepad & BUTTON_DOWN != 0,
+            gamepad & BUTTON_LEFT != 0,
+            gamepad & BUTTON_RIGHT != 0,
+            pressed & BUTTON_1 != 0,
+            toggle_view,
+            strafe
+        )
+    }
+
+    /// Turns a player by `turn` radians (positive is to the left), for aiming with the mouse.
+    pub fn look(&mut self, pidx: usize, turn: f32) {
+        if self.player_life[pidx] > 0 && self.finish_time[pidx].is_none() {
+            self.player_angle[pidx] += turn;
         }
     }
 
@@ -158,16 +603,18 @@ impl State {
     }
 
     /// Moves a player around based on user input.
-    fn update_player(&mut self, pidx: usize, up: bool, down: bool, left: bool, right: bool) {
+    fn update_player(&mut self, pidx: usize, up: bool, down: bool, left: bool, right: bool, strafe: bool) {
         let mut player_x = self.player_x[pidx];
         let mut player_y = self.player_y[pidx];
         let mut player_angle = self.player_angle[pidx];
 
-        // Store the current index in case we need to undo a move.
-        let previous_index = get_index(player_x, player_y, WIDTH, HEIGHT);
-
-        // Enemy players should move more slowly
-        let step_size = if pidx > 0 {STEP_SIZE * 0.65} else {STEP_SIZE};
+        let (step_size, turn_rate) = match self.player_controller[pidx] {
+            Controller::Human => (STEP_SIZE, STEP_SIZE),
+            Controller::Bot => {
+                let skill = self.difficulty.skill();
+                (skill.speed, skill.turn_rate)
+            }
+        };
 
         // Tentative updates to player position and orientation.
         if up {
@@ -178,76 +625,108 @@ impl State {
             player_x -= cosf(player_angle) * step_size;
             player_y -= -sinf(player_angle) * step_size;
         }
-        if right {
-            player_angle -= step_size;
-        }
-        if left {
-            player_angle += step_size;
+        if strafe {
+            // Sidestep at right angles to the way the player is facing
+            if left {
+                player_x -= sinf(player_angle) * step_size;
+                player_y -= cosf(player_angle) * step_size;
+            }
+            if right {
+                player_x += sinf(player_angle) * step_size;
+                player_y += cosf(player_angle) * step_size;
+            }
+        } else {
+            if right {
+                player_angle -= turn_rate;
+            }
+            if left {
+                player_angle += turn_rate;
+            }
         }
 
-        // If the player has moved to a new cell, then new_index will differ from previous_index.
-        let new_index = get_index(player_x, player_y, WIDTH, HEIGHT);
+        // Rather than stopping dead at a wall, slide along it.
+        let (player_x, player_y) = push_out_of_walls(
+            player_x,
+            player_y,
+            PLAYER_RADIUS,
+            &self.horizontal_walls,
+            &self.vertical_walls
+        );
+        self.player_x[pidx] = player_x;
+        self.player_y[pidx] = player_y;
+        self.player_angle[pidx] = player_angle;
+    }
 
-        // Conditionally apply the move.
-        if ( // If move would cause player to leave the maze...
-            (player_x <= 0.0) ||
-            (player_y <= 0.0) ||
-            (player_x as usize >= WIDTH) ||
-            (player_y as usize >= HEIGHT)
-        ) ||
-        ( // ...or if they would go through a wall...
-            (previous_index != new_index) && 
-            there_is_no_passage_here(previous_index, new_index, &self.passages)
-        )
-        { // ... do not apply the move.
-            self.player_angle[pidx] = player_angle;
-        } else {
-            self.player_x[pidx] = player_x;
-            self.player_y[pidx] = player_y;
-            self.player_angle[pidx] = player_angle;
+    /// How many rounds a player's weapon holds. Bots only get to use part of it,
+    /// depending on difficulty.
+    fn magazine(&self, pidx: usize) -> usize {
+        let magazine = self.player_weapon[pidx].stats().magazine.min(BULLETS_PER_PLAYER);
+        match self.player_controller[pidx] {
+            Controller::Human => magazine,
+            Controller::Bot => self.difficulty.skill().magazine.min(magazine)
         }
     }
 
     /// Fires a bullet in response to player input; incrementally reloads spent ammo.
+    /// In the top-down view the fire button switches to the next weapon instead.
     fn update_ammo(&mut self, pidx: usize, shoot: bool) {
-        // Kludge to limit enemies to just one bullet
-        if pidx > 0 {
-            let first_bullet = self.player_ammo[pidx][0];
-            self.player_ammo[pidx] = [Ammo::Reloading(RELOAD_TIME); BULLETS_PER_PLAYER];
-            self.player_ammo[pidx][0] = first_bullet;
+        if shoot && matches!(self.player_view[pidx], View::TopDown) {
+            // A new weapon starts with an empty magazine
+            self.player_weapon[pidx] = self.player_weapon[pidx].next();
+            let reload = self.player_weapon[pidx].stats().reload;
+            self.player_ammo[pidx] = [Ammo::Reloading(reload); BULLETS_PER_PLAYER];
+            tone(600, 4, 60, TONE_PULSE1);
+            return;
         }
 
+        let weapon = self.player_weapon[pidx];
+        let stats = weapon.stats();
+        let magazine = self.magazine(pidx);
+        let aim_error = match self.player_controller[pidx] {
+            Controller::Human => 0.1,
+            Controller::Bot => self.difficulty.skill().aim_error
+        };
+
         // When the player presses the x button.
         if shoot {
             // Find the first loaded ammo
-            match self.player_ammo[pidx].iter_mut().find(|&&mut a| a == Ammo::Loaded) {
+            match self.player_ammo[pidx][..magazine].iter_mut().find(|&&mut a| a == Ammo::Loaded) {
                 Some(ammo) => {
                     // Change it to reloading
-                    *ammo = Ammo::Reloading(RELOAD_TIME);
+                    *ammo = Ammo::Reloading(stats.reload);
                     tone(1000 | (10 << 16), 10, 100, TONE_NOISE);
-                    let mut rng = SmallRng::seed_from_u64(self.seed);
-                    self.seed = rng.gen::<u64>();
-                    let attempt = self.bullets.push(
-                        Bullet::new(
-                            self.player_x[pidx],
-                            self.player_y[pidx],
-                            pidx,
-                            self.player_angle[pidx] + (rng.gen::<f32>() - 0.5)/10.0,
-                            true
-                        )
-                    );
-                    match attempt {
-                        Ok(()) => {},
-                        Err(_) => {}
+                    let mut rng = self.player_rng(pidx, AIM_STREAM);
+                    let aim = self.player_angle[pidx] + (rng.gen::<f32>() - 0.5) * aim_error;
+                    // Pellets fan out evenly across the weapon's spread
+                    for pellet in 0..stats.pellets {
+                        let offset = if stats.pellets > 1 {
+                            stats.spread * (pellet as f32 / (stats.pellets - 1) as f32 - 0.5)
+                        } else {
+                            0.0
+                        };
+                        let mut bullet = Bullet::from_weapon(
+                            weapon, self.player_x[pidx], self.player_y[pidx], pidx, aim + offset, true
+                        );
+                        if self.ricochet {
+                            bullet.bounces = MAX_BOUNCES;
+                        }
+                        self.bullets.push(bullet).ok();
                     }
                 },
                 None => {}
             }
+
+            // Swap in a spare magazine as soon as the last round is gone
+            let empty = self.player_ammo[pidx][..magazine].iter().all(|&a| a != Ammo::Loaded);
+            if empty && self.spare_magazines[pidx] > 0 {
+                self.spare_magazines[pidx] -= 1;
+                self.player_ammo[pidx][..magazine].fill(Ammo::Loaded);
+            }
         }
 
         // Find the first ammo that is not loaded and incrementally reload it.
-        // Spent ammo take RELOAD_TIME frames to reload and are reloaded one at a time.
-        match self.player_ammo[pidx].iter_mut().find(|&&mut a| a != Ammo::Loaded) {
+        // Spent ammo take the weapon's reload time and are reloaded one at a time.
+        match self.player_ammo[pidx][..magazine].iter_mut().find(|&&mut a| a != Ammo::Loaded) {
             Some(ammo) => match ammo {
                 // Decrement time to reload until we reach 0 (means we are loaded)
                 Ammo::Reloading(time_to_reload) => {
@@ -263,55 +742,85 @@ impl State {
         }
     }
 
-    /// Propagates bullets in flight, removing them when they hit a wall.
+    /// Propagates bullets in flight, bouncing them off walls while they have bounces left
+    /// and removing them when they hit one otherwise.
     fn update_bullets(&mut self) {
         // Update the position of each bullet in flight.
         self.bullets.iter_mut().for_each(|b| {
-            let previous_index = get_index(b.x, b.y, WIDTH, HEIGHT);
-            b.x += cosf(b.angle) * BULLET_SPEED;
-            b.y += -sinf(b.angle) * BULLET_SPEED;
-            let new_index = get_index(b.x, b.y, WIDTH, HEIGHT);
+            let (start_x, start_y) = (b.x, b.y);
+            b.x += cosf(b.angle) * b.speed;
+            b.y += -sinf(b.angle) * b.speed;
+            b.range -= b.speed;
+
+            if b.bounces > 0 {
+                // Mirror the bullet in whichever walls it went through: a vertical wall flips
+                // its x direction and a horizontal one its y direction
+                let (column, row) = (floorf(start_x), floorf(start_y));
+                let (new_column, new_row) = (floorf(b.x), floorf(b.y));
+                let line_x = column.max(new_column);
+                let line_y = row.max(new_row);
+                let through_vertical = new_column != column && (
+                    point_in_wall(line_x, row, &self.vertical_walls) ||
+                    point_in_wall(line_x, new_row, &self.vertical_walls)
+                );
+                let through_horizontal = new_row != row && (
+                    point_in_wall(line_y, column, &self.horizontal_walls) ||
+                    point_in_wall(line_y, new_column, &self.horizontal_walls)
+                );
+                if through_vertical {
+                    b.x = 2.0 * line_x - b.x;
+                    b.angle = PI - b.angle;
+                }
+                if through_horizontal {
+                    b.y = 2.0 * line_y - b.y;
+                    b.angle = -b.angle;
+                }
+                if through_vertical || through_horizontal {
+                    b.bounces -= 1;
+                }
+            }
+
+            let previous_index = get_index(start_x, start_y, self.width, self.height);
+            let new_index = get_index(b.x, b.y, self.width, self.height);
             if ( // If bullet leaves the maze...
-                b.x <= 0.0 || b.y <= 0.0 || b.x as usize >= WIDTH || b.y as usize >= HEIGHT
+                b.x <= 0.0 || b.y <= 0.0 || b.x as usize >= self.width || b.y as usize >= self.height
             ) || ( // ...or if it would go through a wall...
                 (previous_index != new_index) && 
-                there_is_no_passage_here(previous_index, new_index, &self.passages)
+                there_is_a_wall_between(previous_index, new_index, self.width, &self.horizontal_walls, &self.vertical_walls)
             ) { // ... mark inflight as false.
                 b.inflight = false;
             }
-            
+            // Rounds that have gone as far as they can just drop
+            if b.range <= 0.0 {
+                b.inflight = false;
+            }
+
             if b.inflight {
-                for pidx in 0..NUM_PLAYERS {
-                    if pidx != b.owner {
-                        let player_index = get_index(
-                            self.player_x[pidx], 
-                            self.player_y[pidx], 
-                            WIDTH, 
-                            HEIGHT
-                        );
-                        if player_index == new_index {
-                            // Get the relative angle between bullet and player
-                            let rise = self.player_y[pidx] - self.player_y[b.owner];
-                            let run = self.player_x[pidx] - self.player_x[b.owner];
-                            let angle_to_player = -1.0 * atan2f(rise, run);
-                            let relative_angle = self.player_angle[pidx] - angle_to_player;
-
-                            // Determine collision window
-                            let striking_distance = PLAYER_WIDTH * fabsf(cosf(relative_angle));
-                            let separation = distance(
-                                b.x - self.player_x[pidx], 
-                                b.y - self.player_y[pidx]
-                            );
-
-                            if separation <= striking_distance {
-                                self.player_life[pidx] -= 1;
-                                if self.player_life[pidx] == 0 && b.owner == 0 {
-                                    self.score += 60;
-                                }
-                                b.inflight = false;
-                            }
+                // Sweep the bullet's path over this step against everyone's hitbox and take
+                // the first one it touches, ignoring hitboxes that poke through a wall
+                let hit = (0..NUM_PLAYERS)
+                    .filter(|&pidx| pidx != b.owner && self.player_life[pidx] > 0)
+                    .filter_map(|pidx| {
+                        let (x, y) = (self.player_x[pidx], self.player_y[pidx]);
+                        let t = sweep_circle(start_x, start_y, b.x, b.y, x, y, PLAYER_WIDTH / 2.0)?;
+                        let contact_x = start_x + (b.x - start_x) * t;
+                        let contact_y = start_y + (b.y - start_y) * t;
+                        let blocked = distance(contact_x - x, contact_y - y) > 0.0 &&
+                            !line_of_sight(contact_x, contact_y, x, y, &self.horizontal_walls, &self.vertical_walls);
+                        (!blocked).then_some((pidx, t))
+                    })
+                    .min_by(|a, b| a.1.total_cmp(&b.1));
+
+                if let Some((pidx, _)) = hit {
+                    self.player_life[pidx] = (self.player_life[pidx] - b.damage).max(0);
+                    self.last_attacker[pidx] = Some(b.owner);
+                    if self.player_life[pidx] == 0 {
+                        self.kills[b.owner] += 1;
+                        if b.owner == 0 {
+                            self.score += 60;
                         }
                     }
+                    b.inflight = false;
                 }
             }
         });
@@ -320,177 +829,306 @@ impl State {
         self.bullets = self.bullets.iter().map(|b| *b).filter(|b| b.inflight == true).collect();
     }
 
-    fn update_enemy(&mut self, pid: usize) -> (bool,bool,bool,bool,bool,bool) {
+    /// Counts down until taken pickups come back, and hands out the ones players walk over.
+    /// A pickup is left where it is for anyone it would do nothing for.
+    fn update_pickups(&mut self) {
+        let magazines: [usize; NUM_PLAYERS] = core::array::from_fn(|pidx| self.magazine(pidx));
+        for pickup in self.pickups.iter_mut() {
+            if !pickup.available() {
+                pickup.respawn -= 1;
+                continue;
+            }
+            for pidx in 0..NUM_PLAYERS {
+                let reach = PLAYER_WIDTH / 2.0;
+                if self.player_life[pidx] <= 0 || self.finish_time[pidx].is_some() ||
+                    distance(self.player_x[pidx] - pickup.x, self.player_y[pidx] - pickup.y) > reach
+                {
+                    continue;
+                }
+                let ammo = &mut self.player_ammo[pidx];
+                let used = match pickup.kind {
+                    PickupKind::Health if self.player_life[pidx] < MAX_LIFE => {
+                        self.player_life[pidx] = (self.player_life[pidx] + 2).min(MAX_LIFE);
+                        true
+                    },
+                    PickupKind::Reload if ammo[..magazines[pidx]].iter().any(|&a| a != Ammo::Loaded) => {
+                        ammo[..magazines[pidx]].fill(Ammo::Loaded);
+                        true
+                    },
+                    PickupKind::Magazine if self.spare_magazines[pidx] < MAX_SPARE_MAGAZINES => {
+                        self.spare_magazines[pidx] += 1;
+                        true
+                    },
+                    _ => false
+                };
+                if used {
+                    pickup.take();
+                    tone(400 | (800 << 16), 8, 80, TONE_PULSE1);
+                    break;
+                }
+            }
+        }
+    }
 
-        let idx = pid - 1;
-        let mut rng = SmallRng::seed_from_u64(self.seed);
-        self.seed = rng.gen::<u64>();
+    /// Returns which of this frame's distance fields leads to `goal`, working it out if no
+    /// other bot has already asked for the same goal.
+    fn distances_to(&mut self, goal: Goal) -> usize {
+        if let Some(slot) = self.field_goals.iter().position(|&g| g == goal) {
+            return slot;
+        }
+        // Each player asks for at most one goal a frame, so there is always a free slot
+        let slot = self.field_goals.len();
+        self.field_goals.push(goal).ok();
+        let cell;
+        let sources = match goal {
+            Goal::Cell(index) => {
+                cell = index;
+                core::slice::from_ref(&cell)
+            },
+            Goal::Exits => &self.exits[..]
+        };
+        distance_field(sources, self.width, self.height, &self.horizontal_walls, &self.vertical_walls, &mut self.fields[slot]);
+        slot
+    }
 
-        let enemy_index = get_index(self.player_x[idx], self.player_y[idx], WIDTH, HEIGHT);
-        let player_index = get_index(self.player_x[0], self.player_y[0], WIDTH, HEIGHT);
+    /// Whether player `idx` can see player `other`: alive, within the field of view and with no wall in the way.
+    fn can_see(&self, idx: usize, other: usize) -> bool {
+        let (x, y) = (self.player_x[idx], self.player_y[idx]);
+        let (other_x, other_y) = (self.player_x[other], self.player_y[other]);
+        other != idx &&
+            self.player_life[other] > 0 &&
+            fabsf(angle_difference(self.player_angle[idx], other_y - y, other_x - x)) <= HALF_FOV &&
+            line_of_sight(x, y, other_x, other_y, &self.horizontal_walls, &self.vertical_walls)
+    }
 
-        self.visited.clear();
-        self.visited.extend_from_slice(&[false;NUM_CELLS]).unwrap();
-        self.paths.clear();
-        self.pruned_path.clear();
-        self.stack.clear();
-
-        State::find_path(
-            enemy_index, 
-            player_index, 
-            WIDTH, HEIGHT, 
-            &mut self.passages, 
-            &mut self.visited, 
-            &mut self.stack, 
-            &mut self.paths, 
-            &mut self.pruned_path, 
-            &mut rng
+    /// Picks who a bot is after. Outside a free-for-all that is the nearest person it can see,
+    /// or whoever it is already hunting while it still knows where they were last seen; in one,
+    /// bots choose among the players they can see, or stick with their current target.
+    fn choose_target(&self, idx: usize) -> Option<usize> {
+        let alive = |pidx: usize| pidx != idx && self.player_life[pidx] > 0;
+        let distance_to = |pidx: usize| distance(
+            self.player_x[pidx] - self.player_x[idx],
+            self.player_y[pidx] - self.player_y[idx]
         );
 
-        let target_index;
-        if self.pruned_path.len() > 1 {
-            target_index = self.pruned_path.remove(1);
-        } else {
-            target_index = enemy_index;
+        if self.mode != GameMode::FreeForAll {
+            return (0..NUM_PLAYERS)
+                .filter(|&pidx| self.player_controller[pidx] == Controller::Human && self.can_see(idx, pidx))
+                .min_by(|&a, &b| distance_to(a).total_cmp(&distance_to(b)))
+                .or(self.targets[idx].filter(|&t| alive(t) && self.last_seen[idx].is_some()));
         }
 
-        let (target_x, target_y) = get_center_from_index(target_index, WIDTH, HEIGHT);
-
-        // Calculate the angle and unwrap
-        let enemy_angle = self.player_angle[idx];
-        let rise = target_y - self.player_y[idx];
-        let run = target_x - self.player_x[idx];
-        let target_angle = -1.0 * atan2f(rise, run);
-
-        let num_wraps = floorf((target_angle - enemy_angle)/(2.0 * PI));
-        let unwrapped = target_angle - 2.0 * PI * num_wraps;
-        let extra_unwrapped = unwrapped - 2.0 * PI;
+        // Being shot gives away where the shooter is
+        if TARGETING[idx] == Targeting::LastAttacker {
+            if let Some(attacker) = self.last_attacker[idx].filter(|&a| alive(a)) {
+                return Some(attacker);
+            }
+        }
 
-        // Sometimes unwrapping is off by one (end condition)
-        let extra_is_closer = fabsf(enemy_angle - unwrapped) > fabsf(enemy_angle - extra_unwrapped);
-        let unwrapped_angle = if extra_is_closer {
-            extra_unwrapped
-        } else {
-            unwrapped
+        let seen = (0..NUM_PLAYERS).filter(|&pidx| self.can_see(idx, pidx));
+        let choice = match TARGETING[idx] {
+            Targeting::Weakest => seen.min_by(|&a, &b| {
+                self.player_life[a].cmp(&self.player_life[b]).then(distance_to(a).total_cmp(&distance_to(b)))
+            }),
+            Targeting::Nearest | Targeting::LastAttacker => seen.min_by(|&a, &b| distance_to(a).total_cmp(&distance_to(b)))
         };
+        choice.or(self.targets[idx].filter(|&t| alive(t)))
+    }
 
-        let angle_diff = unwrapped_angle - enemy_angle;
+    fn update_enemy(&mut self, pid: usize) -> (bool,bool,bool,bool,bool,bool,bool) {
 
-        // let mut data = String::<32>::new();
-        // if pid == 2 {
-        //     write!(data, "1: {angle_diff}, 2: {enemy_angle}\n").unwrap();
-        //     trace(data);
-        // }
+        let idx = pid - 1;
+        let mut rng = self.player_rng(idx, PATH_STREAM);
+        let skill = self.difficulty.skill();
+        let (enemy_x, enemy_y) = (self.player_x[idx], self.player_y[idx]);
+        let enemy_index = get_index(enemy_x, enemy_y, self.width, self.height);
 
-        let distance_to_player = distance(
-            self.player_y[0] - self.player_y[idx],
-            self.player_x[0] - self.player_x[idx] 
-        );
-        let fire = distance_to_player <= 3.0;
+        // A bot going after someone new only knows where they are once it sees them
+        let target = self.choose_target(idx);
+        if target != self.targets[idx] {
+            self.targets[idx] = target;
+            self.last_seen[idx] = None;
+        }
+        let target = target.unwrap_or(idx);
 
-        if fabsf(angle_diff) <= 0.08 {
-            let target_distance = distance(rise, run);
-            if target_distance < 0.5 {
-                (false,false,false,false,fire,false)
-            } else {
-                (true,false,false,false,fire,false)
-            }
-        } else if angle_diff > 0.08 {
-            (false,false,true,false,false,false)
+        let rise = self.player_y[target] - enemy_y;
+        let run = self.player_x[target] - enemy_x;
+        let distance_to_player = distance(rise, run);
+        let angle_to_player = angle_difference(self.player_angle[idx], rise, run);
+        let visible = self.can_see(idx, target);
+        if visible {
+            self.last_seen[idx] = Some(get_index(self.player_x[target], self.player_y[target], self.width, self.height));
+        }
+
+        // Only shoot once the target has been in sight and range long enough to react
+        let in_range = visible && distance_to_player <= skill.fire_range;
+        if in_range {
+            self.reaction_timers[idx] = self.reaction_timers[idx].saturating_add(1);
         } else {
-            (false,false,false,true,false,false)
-        }
-    }
-
-    fn find_path<const M: usize, const N: usize>(
-        start: usize,
-        end: usize,
-        width: usize, 
-        height: usize, 
-        passages: &mut Vec<(usize,usize),N>,
-        visited: &mut Vec<bool,M>,
-        stack: &mut Vec<usize,N>,
-        paths: &mut Vec<usize,N>,
-        pruned_path: &mut Vec<usize,N>,
-        rng: &mut SmallRng
-    ) {
-    
-        visited[start] = true;
-        stack.push(start).unwrap();
-        let mut still_looking = true;
-        let mut checkpoint = start;
-    
-        while let Some(node) = stack.pop() {
-            if still_looking {
-                visited[node] = true;
-                let neighbors = State::find_neighbors(node, width, height);
-                let mut potential_paths: Vec<usize,4> = neighbors.into_iter()
-                    .flatten() // Option implements IntoIter
-                    .filter(|&n| visited[n] == false)
-                    .filter(|&n| there_is_no_passage_here(node, n, passages) == false)
-                    .collect();
-                potential_paths.shuffle(rng);
-    
-                if node == end {
-                    paths.push(node).unwrap();
-                    still_looking = false;
-                } else if potential_paths.len() > 1 {
-                    checkpoint = node;
-                    paths.push(node).unwrap();
-                } else if potential_paths.len() == 0 {
-                    while let Some(p) = paths.pop() {
-                        if p == checkpoint {
-                            paths.push(checkpoint).unwrap();
-                            break;
-                        }
-                    }
+            self.reaction_timers[idx] = 0;
+        }
+
+        // Fast turners need a wider window so they don't overshoot back and forth
+        let tolerance = skill.turn_rate.max(0.08);
+        // Pickups reload instantly, so also hold fire while a magazine's worth is still in flight
+        let in_flight = self.bullets.iter().filter(|b| b.owner == idx).count();
+        let volley = self.magazine(idx) * self.player_weapon[idx].stats().pellets;
+        let fire = in_range && self.reaction_timers[idx] > skill.reaction && fabsf(angle_to_player) <= tolerance
+            && in_flight < volley;
+
+        // Racers just run for the exit, taking any shot that lines up on the way
+        if self.mode == GameMode::Race {
+            return self.step_toward(idx, Goal::Exits, tolerance, fire);
+        }
+
+        if self.player_life[idx] > FLEE_LIFE {
+            self.hideouts[idx] = None;
+        }
+        self.player_brain[idx] = self.think(idx, in_range);
+        match self.player_brain[idx] {
+            Brain::Engage => steer(angle_to_player, tolerance, false, fire),
+            Brain::Reload => {
+                // Keep facing a visible target while backing away from it
+                if visible {
+                    let (_, _, left, right, _, _, _) = steer(angle_to_player, tolerance, false, false);
+                    (false, in_range, left, right, false, false, false)
                 } else {
-                    paths.push(node).unwrap();
+                    (false,false,false,false,false,false,false)
                 }
-    
-                for pass in potential_paths {
-                    stack.push(pass).unwrap();
+            },
+            Brain::Flee => match self.hideouts[idx] {
+                Some(hideout) if hideout == enemy_index => {
+                    // Back into the dead end, facing the only way in
+                    let entrance = open_neighbors(enemy_index, self.width, self.height, &self.horizontal_walls, &self.vertical_walls)
+                        .first().copied().unwrap_or(enemy_index);
+                    let (entrance_x, entrance_y) = get_center_from_index(entrance, self.width, self.height);
+                    let angle_diff = angle_difference(self.player_angle[idx], entrance_y - enemy_y, entrance_x - enemy_x);
+                    steer(angle_diff, tolerance, false, fire)
+                },
+                Some(hideout) => self.step_toward(idx, Goal::Cell(hideout), tolerance, fire),
+                None => {
+                    self.hideouts[idx] = Some(self.find_hideout(idx));
+                    (false,false,false,false,false,false,false)
                 }
-            }
-        }
-    
-        let mut last_node = paths.pop().unwrap();
-        pruned_path.insert(0, last_node).unwrap();
-        while let Some(node) = paths.pop() {
-            if there_is_no_passage_here(last_node, node, passages) == false {
-                pruned_path.insert(0, node).unwrap();
-                last_node = node;
+            },
+            Brain::Hunt => match self.last_seen[idx] {
+                // Got there and nobody is around
+                Some(cell) if cell == enemy_index => {
+                    self.last_seen[idx] = None;
+                    (false,false,false,false,false,false,false)
+                },
+                Some(cell) => self.step_toward(idx, Goal::Cell(cell), tolerance, fire),
+                None => (false,false,false,false,false,false,false)
+            },
+            Brain::Wander => {
+                if self.wander_goals[idx].is_none() || self.wander_goals[idx] == Some(enemy_index) {
+                    self.wander_goals[idx] = Some(rng.gen_range(0..self.width * self.height));
+                }
+                let goal = Goal::Cell(self.wander_goals[idx].unwrap_or(enemy_index));
+                self.step_toward(idx, goal, tolerance, fire)
             }
         }
     }
 
-    fn find_neighbors(index: usize, width: usize, height: usize) -> [Option<usize>;4] {
-        let num_cells = width * height;
-    
-        let up = if index < num_cells - width {
-            Some(index + width)
-        } else {
-            None
-        };
-    
-        let down = if index > width - 1 {
-            Some(index - width)
-        } else {
-            None
-        };
-    
-        let left = if index % width != 0 {
-            Some(index - 1)
-        } else {
-            None
-        };
-    
-        let right = if (index + 1) % width != 0 {
-            Some(index + 1)
+    /// Picks a bot's next state from its health, its ammo and whether its target is in range.
+    fn think(&self, idx: usize, in_range: bool) -> Brain {
+        let magazine = self.magazine(idx);
+        let loaded = self.player_ammo[idx][..magazine].iter().filter(|&&a| a == Ammo::Loaded).count();
+        // No point running from someone who is just as badly hurt
+        let outmatched = self.targets[idx].is_none_or(|t| self.player_life[t] > self.player_life[idx]);
+
+        if self.player_life[idx] <= FLEE_LIFE && outmatched {
+            // Fight back with whatever is loaded rather than turning tail on someone in range
+            if in_range && loaded > 0 { Brain::Engage } else { Brain::Flee }
+        } else if loaded == 0 || (self.player_brain[idx] == Brain::Reload && loaded < magazine) {
+            Brain::Reload
+        } else if in_range {
+            Brain::Engage
+        } else if self.last_seen[idx].is_some() {
+            Brain::Hunt
         } else {
-            None
+            Brain::Wander
+        }
+    }
+
+    /// Finds somewhere for a bot to hide: the dead end furthest from where its target was
+    /// last seen (or from the bot itself), or just the furthest cell in a maze without dead ends.
+    fn find_hideout(&mut self, idx: usize) -> usize {
+        let here = get_index(self.player_x[idx], self.player_y[idx], self.width, self.height);
+        let threat = self.last_seen[idx].unwrap_or(here);
+        let distances = &self.fields[self.distances_to(Goal::Cell(threat))];
+        (0..self.width * self.height)
+            .filter(|&cell| distances[cell] != UNREACHABLE)
+            .max_by_key(|&cell| {
+                let exits = open_neighbors(cell, self.width, self.height, &self.horizontal_walls, &self.vertical_walls).len();
+                (exits == 1, distances[cell])
+            })
+            .unwrap_or(here)
+    }
+
+    /// Turns and moves a bot one cell along a shortest route to `goal`. When there are several
+    /// equally short routes it takes the one needing the least turning, so it doesn't dither
+    /// between them.
+    fn step_toward(
+        &mut self,
+        idx: usize,
+        goal: Goal,
+        tolerance: f32,
+        fire: bool
+    ) -> (bool,bool,bool,bool,bool,bool,bool) {
+        let (enemy_x, enemy_y) = (self.player_x[idx], self.player_y[idx]);
+        let enemy_index = get_index(enemy_x, enemy_y, self.width, self.height);
+        let distances = &self.fields[self.distances_to(goal)];
+        let turn_to = |cell: usize| {
+            let (x, y) = get_center_from_index(cell, self.width, self.height);
+            fabsf(angle_difference(self.player_angle[idx], y - enemy_y, x - enemy_x))
         };
-    
-        return [up, down, left, right];
+
+        let here = distances[enemy_index];
+        let target_index = open_neighbors(enemy_index, self.width, self.height, &self.horizontal_walls, &self.vertical_walls)
+            .into_iter()
+            .filter(|&n| here != UNREACHABLE && distances[n] < here)
+            .min_by(|&a, &b| turn_to(a).total_cmp(&turn_to(b)))
+            .unwrap_or(enemy_index);
+
+        let (target_x, target_y) = get_center_from_index(target_index, self.width, self.height);
+        let rise = target_y - enemy_y;
+        let run = target_x - enemy_x;
+        let angle_diff = angle_difference(self.player_angle[idx], rise, run);
+        steer(angle_diff, tolerance, distance(rise, run) >= 0.5, fire)
+    }
+}
+
+/// What a bot is up to in a fight.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Brain {
+    /// Nobody to go after, so explore the maze.
+    Wander,
+    /// Head for where the target was last seen.
+    Hunt,
+    /// Stand and shoot at a target in range.
+    Engage,
+    /// Low on life: run to a dead end far from the target and hold it.
+    Flee,
+    /// Out of ammo: back off until the magazine is full again.
+    Reload
+}
+
+/// Where a bot is trying to get to.
+#[derive(Clone, Copy, PartialEq)]
+enum Goal {
+    Cell(usize),
+    /// Whichever exit is nearest.
+    Exits
+}
+
+/// Turns a bot toward an angle, moving forward (if `advance`) and firing once it is lined up.
+fn steer(angle_diff: f32, tolerance: f32, advance: bool, fire: bool) -> (bool,bool,bool,bool,bool,bool,bool) {
+    if fabsf(angle_diff) <= tolerance {
+        (advance,false,false,false,fire,false,false)
+    } else if angle_diff > tolerance {
+        (false,false,true,false,false,false,false)
+    } else {
+        (false,false,false,true,false,false,false)
     }
 }
\ No newline at end of file
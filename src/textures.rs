@@ -0,0 +1,53 @@
+// Wall textures for the first-person view, stored like 2bpp sprites: four pixels to a byte,
+// leftmost pixel in the highest bits. Each pixel is a palette index (0 to 3 for colours 1 to 4).
+
+pub const TEXTURE_SIZE: usize = 16; // pixels along each side
+pub const TEXTURE_BYTES: usize = TEXTURE_SIZE * TEXTURE_SIZE / 4;
+
+/// Horizontal faces: rows of bricks (colour 3) laid in pink mortar (colour 2).
+pub const BRICKS: [u8; TEXTURE_BYTES] = [
+    0b01010101, 0b01010101, 0b01010101, 0b01010101,
+    0b01101010, 0b10101010, 0b10101010, 0b10101010,
+    0b01101010, 0b10101010, 0b10101010, 0b10101010,
+    0b01101010, 0b01101010, 0b10101010, 0b10101010,
+    0b01101010, 0b10101010, 0b10101010, 0b10101010,
+    0b01101010, 0b10101010, 0b10101010, 0b01101010,
+    0b01101010, 0b10101010, 0b10101010, 0b10101010,
+    0b01101010, 0b10101010, 0b10101010, 0b10101010,
+    0b01010101, 0b01010101, 0b01010101, 0b01010101,
+    0b10101010, 0b10101010, 0b01101010, 0b10101010,
+    0b10101010, 0b10101010, 0b01101010, 0b10101010,
+    0b10101010, 0b10101010, 0b01101010, 0b10011010,
+    0b10100110, 0b10101010, 0b01101010, 0b10101010,
+    0b10101010, 0b10101010, 0b01101010, 0b10101010,
+    0b10101010, 0b10100110, 0b01101010, 0b10101010,
+    0b10101010, 0b10101010, 0b01101010, 0b10101010,
+];
+
+/// Vertical faces: big pink blocks (colour 2), lit along the top, with dark seams (colour 4).
+pub const BLOCKS: [u8; TEXTURE_BYTES] = [
+    0b10101010, 0b10101010, 0b10101010, 0b10101011,
+    0b01010101, 0b01010101, 0b01010101, 0b01010111,
+    0b01010101, 0b01010101, 0b01010101, 0b01010111,
+    0b01010101, 0b01010101, 0b01010101, 0b01010111,
+    0b01010101, 0b01010101, 0b01010101, 0b01010111,
+    0b01010101, 0b01010101, 0b01010101, 0b01010111,
+    0b01010101, 0b01010101, 0b01010101, 0b01010111,
+    0b11111111, 0b11111111, 0b11111111, 0b11111111,
+    0b10101010, 0b10101011, 0b10101010, 0b10101010,
+    0b01010101, 0b01010111, 0b01010101, 0b01010101,
+    0b01010101, 0b01010111, 0b01010101, 0b01010101,
+    0b01010101, 0b01010111, 0b01010101, 0b01010101,
+    0b01010101, 0b01010111, 0b01010101, 0b01010101,
+    0b01010101, 0b01010111, 0b01010101, 0b01010101,
+    0b01010101, 0b01010111, 0b01010101, 0b01010101,
+    0b11111111, 0b11111111, 0b11111111, 0b11111111,
+];
+
+/// The palette index at (`u`, `v`) in a texture, where both run from 0.0 to 1.0 across it.
+pub fn texel(texture: &[u8; TEXTURE_BYTES], u: f32, v: f32) -> u8 {
+    let column = ((u * TEXTURE_SIZE as f32) as usize).min(TEXTURE_SIZE - 1);
+    let row = ((v * TEXTURE_SIZE as f32) as usize).min(TEXTURE_SIZE - 1);
+    let index = row * TEXTURE_SIZE + column;
+    (texture[index / 4] >> (6 - 2 * (index % 4))) & 0b11
+}
\ No newline at end of file
@@ -1,10 +1,29 @@
-use libm::{sqrtf};
+use core::f32::consts::PI;
+use libm::{atan2f, fabsf, floorf, sqrtf};
 use heapless::{Vec};
 
 pub fn distance(a: f32, b: f32) -> f32 {
     sqrtf((a * a) + (b * b))
 }
 
+/// How far to turn from `angle` to face along (`rise`, `run`), between -PI and PI.
+pub fn angle_difference(angle: f32, rise: f32, run: f32) -> f32 {
+    // Calculate the angle and unwrap
+    let target_angle = -atan2f(rise, run);
+    let num_wraps = floorf((target_angle - angle)/(2.0 * PI));
+    let unwrapped = target_angle - 2.0 * PI * num_wraps;
+    let extra_unwrapped = unwrapped - 2.0 * PI;
+
+    // Sometimes unwrapping is off by one (end condition)
+    let extra_is_closer = fabsf(angle - unwrapped) > fabsf(angle - extra_unwrapped);
+    let unwrapped_angle = if extra_is_closer {
+        extra_unwrapped
+    } else {
+        unwrapped
+    };
+    unwrapped_angle - angle
+}
+
 pub fn get_index(x: f32, y: f32, width: usize, height: usize) -> usize {
     (x as usize) + (y as usize) * width
 }
@@ -15,9 +34,31 @@ pub fn get_center_from_index(index: usize, width: usize, height: usize) -> (f32,
     (x,y)
 }
 
-pub fn point_in_wall<const N: usize>(d1: f32, d2: f32, walls: &Vec<u16,N>) -> bool {
+pub fn point_in_wall<const N: usize>(d1: f32, d2: f32, walls: &Vec<u32,N>) -> bool {
     match walls.get(d1 as usize) {
-        Some(line) => (line & (0b1 << d2 as usize)) != 0,
+        Some(line) => (d2 as usize) < 32 && (line & (0b1 << d2 as usize)) != 0,
         None => true
     }
+}
+
+/// How far (0.0 to 1.0) along the segment from (`x0`, `y0`) to (`x1`, `y1`) it first touches
+/// the circle of `radius` around (`cx`, `cy`), or None if it misses. A segment that starts
+/// inside the circle touches it straight away.
+pub fn sweep_circle(x0: f32, y0: f32, x1: f32, y1: f32, cx: f32, cy: f32, radius: f32) -> Option<f32> {
+    let (dx, dy) = (x1 - x0, y1 - y0);
+    let (fx, fy) = (x0 - cx, y0 - cy);
+
+    // Solve |f + t*d| = radius for t
+    let a = dx * dx + dy * dy;
+    let b = 2.0 * (fx * dx + fy * dy);
+    let c = fx * fx + fy * fy - radius * radius;
+    if c <= 0.0 {
+        return Some(0.0);
+    }
+    let discriminant = b * b - 4.0 * a * c;
+    if a == 0.0 || discriminant < 0.0 {
+        return None;
+    }
+    let t = (-b - sqrtf(discriminant)) / (2.0 * a);
+    (0.0..=1.0).contains(&t).then_some(t)
 }
\ No newline at end of file
@@ -1,13 +1,34 @@
-use core::f32::consts::{PI, FRAC_PI_2};
-use libm::{ceilf, cosf, fabsf, floorf, tanf, atan2f, sinf, powf};
+use libm::{cosf, fabsf, floorf, roundf, atan2f, sinf};
 use heapless::Vec;
 
 use crate::constants::{
-    HEIGHT, WIDTH, HALF_FOV, ANGLE_STEP, WALL_HEIGHT, 
-    NUM_BULLETS, BULLETS_PER_PLAYER, NUM_PLAYERS, PLAYER_WIDTH
+    MAX_HEIGHT, MAX_WIDTH, HALF_FOV, ANGLE_STEP, WALL_HEIGHT, 
+    NUM_BULLETS, BULLETS_PER_PLAYER, NUM_PLAYERS, PLAYER_WIDTH, NUM_PICKUPS, PICKUP_SIZE
 };
-use crate::util::{distance, point_in_wall};
-use crate::arms::{Bullet, Ammo};
+use crate::util::{angle_difference, distance};
+
+// Palette indices for the floor and ceiling
+const FLOOR_LIGHT: u8 = 0;
+const FLOOR_DARK: u8 = 2;
+const CEILING: u8 = 0;
+const CEILING_LINE_COLOUR: u8 = 1;
+// Width of the ceiling lines, in cells
+const CEILING_LINE: f32 = 0.06;
+
+/// Palette index that things fade into with distance (the background colour)
+pub const FOG: u8 = 0;
+// Distances at which fog starts to show and at which it hides everything
+const FOG_START: f32 = 2.0;
+const FOG_END: f32 = 14.0;
+// 4x4 Bayer matrix: the order in which pixels of each 4x4 block give way to fog
+const BAYER: [[u8; 4]; 4] = [
+    [0, 8, 2, 10],
+    [12, 4, 14, 6],
+    [3, 11, 1, 9],
+    [15, 7, 13, 5]
+];
+use crate::arms::{Bullet, Ammo, Weapon};
+use crate::pickups::{Pickup, PickupKind};
 
 /// Filters other players by a player's field of view and returns drawing information. 
 pub fn get_player_view(
@@ -28,24 +49,12 @@ pub fn get_player_view(
         if index != player_index {
             let alive = player_life[index] > 0;
             if alive {
-                // TODO: Refactor into function and reuse here and get_bullet_view
                 let rise = player_y[index] - player_y[player_index];
                 let run = player_x[index] - player_x[player_index];
                 let distance_to_player = distance(rise, run);
 
-                let angle_to_player = -1.0 * atan2f(rise, run);
-                let num_wraps = floorf((angle_to_player - player_angle[player_index])/(2.0 * PI));
-                let unwrapped = angle_to_player - 2.0 * PI * num_wraps;
-                let extra_unwrapped = unwrapped - 2.0 * PI;
-
-                let unwrap_diff = fabsf(player_angle[player_index] - unwrapped);
-                let extra_diff = fabsf(player_angle[player_index] - extra_unwrapped);
-                let extra_is_closer = extra_diff < unwrap_diff;
-                let unwrapped_angle = if extra_is_closer {
-                    extra_unwrapped
-                } else {
-                    unwrapped
-                };
+                // The angle to the player, unwrapped to within half a turn of where we are facing
+                let angle_to_player = player_angle[player_index] + angle_difference(player_angle[player_index], rise, run);
 
                 // Determine how large the player should appear
                 let size = (PLAYER_WIDTH / distance_to_player / ANGLE_STEP) as u32;
@@ -56,19 +65,21 @@ pub fn get_player_view(
                 let width = (( size as f32 ) * fabsf(cosf(player_angle[index] - angle_to_player))) as u32;
 
                 // Check if the angle falls in the FOV
-                if unwrapped_angle >= fov_lower_limit - fov_correction && 
-                    unwrapped_angle <= fov_upper_limit + fov_correction 
+                if angle_to_player >= fov_lower_limit - fov_correction && 
+                    angle_to_player <= fov_upper_limit + fov_correction 
                 {
                     // Determine where the FOV the bullet falls
-                    let h_position = ((fov_upper_limit - unwrapped_angle) / ANGLE_STEP) as i32 - correction;
+                    let h_position = ((fov_upper_limit - angle_to_player) / ANGLE_STEP) as i32 - correction;
 
                     // Vertical correction to account for size
                     let v_position = 80 - ( size as f32 / 2.0 ) as i32;
 
                     // Is this player facing me?
-                    let sum_of_squares = 
-                        powf(cosf(player_angle[index]) + cosf(angle_to_player), 2.0) +
-                        powf(sinf(player_angle[index]) + sinf(angle_to_player), 2.0);
+                    let (cos_sum, sin_sum) = (
+                        cosf(player_angle[index]) + cosf(angle_to_player),
+                        sinf(player_angle[index]) + sinf(angle_to_player)
+                    );
+                    let sum_of_squares = cos_sum * cos_sum + sin_sum * sin_sum;
                     let facing_me = sum_of_squares < 2.0;
 
                     // Update the view for this player with this index
@@ -83,7 +94,7 @@ pub fn get_player_view(
 }
 
 /// Returns information for drawing a player's ammunition dashboard
-pub fn get_ammo_view(player_ammo: [Ammo; BULLETS_PER_PLAYER]) -> [(i32, i32, u32, i32, u32); BULLETS_PER_PLAYER] {
+pub fn get_ammo_view(player_ammo: [Ammo; BULLETS_PER_PLAYER], weapon: Weapon) -> [(i32, i32, u32, i32, u32); BULLETS_PER_PLAYER] {
 
     // Each player ammunition is represented by x and y positions, size, correction, and status.
     let mut ammo_dashboard: [(i32, i32, u32, i32, u32); BULLETS_PER_PLAYER] = [
@@ -94,15 +105,19 @@ pub fn get_ammo_view(player_ammo: [Ammo; BULLETS_PER_PLAYER]) -> [(i32, i32, u32
         (150, 4, 8, 0, 0)
     ];
 
-    // Determine status for each ammo
+    // Determine status for each ammo, in four steps of the weapon's reload time
+    let stats = weapon.stats();
     for (index, ammo) in player_ammo.iter().enumerate() {
+        // Rounds the weapon cannot hold are not drawn at all
+        if index >= stats.magazine {
+            ammo_dashboard[index].2 = 0;
+            continue;
+        }
         let status = match ammo {
             Ammo::Loaded => 8,
-            Ammo::Reloading(time_to_reload) => match time_to_reload {
-                193..=255 => 0,
-                127..=192 => 2,
-                65..=128 => 4,
-                _ => 6
+            Ammo::Reloading(time_to_reload) => {
+                let elapsed = (stats.reload - (*time_to_reload).min(stats.reload)) as u32;
+                elapsed * 4 / (stats.reload as u32 + 1) * 2
             }
         };
         // We need a correction to get concentric circles as status changes
@@ -139,19 +154,8 @@ pub fn get_bullet_view(
             let run = bullet.x - player_x;
             let bullet_distance = distance(rise, run);
 
-            // Calculate the angle and unwrap
-            let bullet_angle = -1.0 * atan2f(rise, run);
-            let num_wraps = floorf((bullet_angle - player_angle)/(2.0 * PI));
-            let unwrapped = bullet_angle - 2.0 * PI * num_wraps;
-            let extra_unwrapped = unwrapped - 2.0 * PI;
-
-            // Sometimes unwrapping is off by one (end condition)
-            let extra_is_closer = fabsf(player_angle - unwrapped) > fabsf(player_angle - extra_unwrapped);
-            let unwrapped_angle = if extra_is_closer {
-                extra_unwrapped
-            } else {
-                unwrapped
-            };
+            // The angle to the bullet, unwrapped to within half a turn of where the player is facing
+            let unwrapped_angle = player_angle + angle_difference(player_angle, rise, run);
 
             // Check if the angle falls in the FOV
             if unwrapped_angle >= fov_lower_limit && unwrapped_angle <= fov_upper_limit {
@@ -173,7 +177,145 @@ pub fn get_bullet_view(
     return ovals;
 }
 
-/// Returns 160 wall heights and their "color" from the player's perspective.
+/// Filters pickups by player's field of view and returns their size and position on screen.
+/// Pickups sit on the floor, and ones that have been taken are not shown.
+pub fn get_pickup_view(
+    player_angle: f32,
+    player_x: f32,
+    player_y: f32,
+    pickups: &Vec<Pickup,NUM_PICKUPS>
+) -> [(i32, i32, u32, f32, Option<PickupKind>); NUM_PICKUPS] {
+
+    let fov_upper_limit = player_angle + HALF_FOV;
+    let fov_lower_limit = fov_upper_limit - (159.0 * ANGLE_STEP);
+
+    // Each pickup defined by: x position, y position, size, distance, and what it is (if visible)
+    let mut sprites = [(0, 0, 0, 0.0, None); NUM_PICKUPS];
+
+    for (index, pickup) in pickups.iter().enumerate() {
+        if pickup.available() {
+            let rise = pickup.y - player_y;
+            let run = pickup.x - player_x;
+            let pickup_distance = distance(rise, run);
+
+            // The angle to the pickup, unwrapped to within half a turn of where the player is facing
+            let unwrapped_angle = player_angle + angle_difference(player_angle, rise, run);
+
+            // Determine how large the pickup should appear, letting it overhang the edges
+            let size = (PICKUP_SIZE / pickup_distance / ANGLE_STEP) as u32;
+            let fov_correction = ANGLE_STEP * (size as f32 / 2.0);
+
+            if unwrapped_angle >= fov_lower_limit - fov_correction &&
+                unwrapped_angle <= fov_upper_limit + fov_correction
+            {
+                let h_position = ((fov_upper_limit - unwrapped_angle) / ANGLE_STEP) as i32 - (size / 2) as i32;
+
+                // Rest it on the floor, which is half a wall below the middle of the screen
+                let floor = 80 + (WALL_HEIGHT / 2.0 / pickup_distance) as i32;
+                let v_position = floor - size as i32;
+
+                sprites[index] = (h_position, v_position, size, pickup_distance, Some(pickup.kind));
+            }
+        }
+    }
+
+    sprites
+}
+
+/// How thick the fog is at `distance`, from 0 (clear) to 16 (nothing shows through).
+pub fn fog_level(distance: f32) -> u8 {
+    ((distance - FOG_START) / (FOG_END - FOG_START) * 16.0).clamp(0.0, 16.0) as u8
+}
+
+/// The colour to draw at screen position (`x`, `y`) for something of colour `colour`
+/// seen through fog of `level`: ordered dithering swaps more and more pixels for fog.
+pub fn fogged(x: i32, y: i32, colour: u8, level: u8) -> u8 {
+    if BAYER[(y & 3) as usize][(x & 3) as usize] < level { FOG } else { colour }
+}
+
+/// Draws a sprite `distance` away with its top-left corner at (`x`, `y`) on screen, one column
+/// at a time: columns where `depth` (the distance to the wall in each column) is closer are left
+/// out, as is anything off the edge of the screen. `shade` gives the palette index of each pixel
+/// of the sprite from its top-left corner, or None where it is see-through.
+#[allow(clippy::too_many_arguments)]
+pub fn draw_sprite<F: Fn(u32, u32) -> Option<u8>>(
+    framebuffer: &mut [u8; 6400],
+    depth: &[f32; 160],
+    x: i32,
+    y: i32,
+    width: u32,
+    height: u32,
+    distance: f32,
+    shade: F
+) {
+    let level = fog_level(distance);
+    for column in x.max(0)..(x + width as i32).min(160) {
+        if depth[column as usize] <= distance {
+            continue;
+        }
+        for row in y.max(0)..(y + height as i32).min(160) {
+            if let Some(colour) = shade((column - x) as u32, (row - y) as u32) {
+                set_pixel(framebuffer, column, row, fogged(column, row, colour, level));
+            }
+        }
+    }
+}
+
+/// Whether (`x`, `y`) from the top-left corner of a `size` square falls in the circle inside it.
+pub fn in_circle(x: u32, y: u32, size: u32) -> bool {
+    let radius = size as f32 / 2.0;
+    let dx = x as f32 + 0.5 - radius;
+    let dy = y as f32 + 0.5 - radius;
+    dx * dx + dy * dy <= radius * radius
+}
+
+/// Writes a palette index (0 to 3 for colours 1 to 4) into a 160x160 2bpp framebuffer,
+/// which packs four pixels to a byte with the leftmost in the lowest bits.
+pub fn set_pixel(framebuffer: &mut [u8; 6400], x: i32, y: i32, colour: u8) {
+    let index = (y * 160 + x) as usize;
+    let shift = (index % 4) * 2;
+    let byte = &mut framebuffer[index / 4];
+    *byte = (*byte & !(0b11 << shift)) | (colour << shift);
+}
+
+/// Paints the floor and ceiling of the first-person view into `framebuffer`: the floor is
+/// checkered cell by cell and the ceiling has a line along each wall. Each screen row below the
+/// horizon sees the floor at one distance (the one where a wall's foot would be on that row),
+/// and the row mirrored above it sees the ceiling at the same place. Both fade into fog
+/// with distance like everything else.
+pub fn draw_floor_and_ceiling(player_angle: f32, player_x: f32, player_y: f32, framebuffer: &mut [u8; 6400]) {
+    // How far each column's ray moves in x and y, and in all, per unit of distance straight
+    // ahead, undoing the fisheye correction the walls get
+    let starting_angle = player_angle + HALF_FOV;
+    let mut rays = [(0.0, 0.0, 0.0); 160];
+    for (idx, ray) in rays.iter_mut().enumerate() {
+        let angle = starting_angle - idx as f32 * ANGLE_STEP;
+        let stretch = cosf(angle - player_angle);
+        *ray = (cosf(angle) / stretch, -sinf(angle) / stretch, 1.0 / stretch);
+    }
+
+    for row in 80..160 {
+        let straight_ahead = WALL_HEIGHT / 2.0 / (row as f32 - 79.5);
+        for (column, (dx, dy, length)) in rays.iter().enumerate() {
+            let x = player_x + dx * straight_ahead;
+            let y = player_y + dy * straight_ahead;
+            let level = fog_level(length * straight_ahead);
+            let column = column as i32;
+
+            let checker = (floorf(x) + floorf(y)) as i32 & 1 == 0;
+            let colour = if checker { FLOOR_LIGHT } else { FLOOR_DARK };
+            set_pixel(framebuffer, column, row, fogged(column, row, colour, level));
+
+            let on_line = x - floorf(x) < CEILING_LINE || y - floorf(y) < CEILING_LINE;
+            let colour = if on_line { CEILING_LINE_COLOUR } else { CEILING };
+            set_pixel(framebuffer, column, 159 - row, fogged(column, 159 - row, colour, level));
+        }
+    }
+}
+
+/// Returns 160 wall heights and their "color" from the player's perspective,
+/// flagging the walls that belong to an exit cell. The last element is where along the wall
+/// (0.0 to 1.0) each ray struck, for texturing.
 /// Source: https://github.com/grantshandy/wasm4-raycaster/blob/main/src/lib.rs
 /// Copyright (c) 2023 Grant Handy
 /// MIT License
@@ -181,32 +323,33 @@ pub fn get_wall_view(
     player_angle: f32,
     player_x: f32,
     player_y: f32,
-    horizontal_walls: &Vec<u16, { HEIGHT + 1 }>,
-    vertical_walls: &Vec<u16, { WIDTH + 1 }>,
-) -> [(i32, f32, bool); 160] {
+    horizontal_walls: &Vec<u32, { MAX_HEIGHT + 1 }>,
+    vertical_walls: &Vec<u32, { MAX_WIDTH + 1 }>,
+    exits: &[usize],
+) -> [(i32, f32, bool, bool, f32); 160] {
     // The player's FOV is split in half by their viewing angle.
     // In order to get the ray's starting angle we must
     // add half the FOV to the player's angle to get
     // the edge of the player's FOV.
     let starting_angle = player_angle + HALF_FOV;
 
-    let mut walls = [(0, 0.0, false); 160];
+    // There is one more line of walls than there are cells
+    let width = vertical_walls.len().saturating_sub(1);
+    let height = horizontal_walls.len().saturating_sub(1);
+
+    let mut walls = [(0, 0.0, false, false, 0.0); 160];
 
     for (idx, wall) in walls.iter_mut().enumerate() {
         // `idx` is what number ray we are, `wall` is
         // a mutable reference to a value in `walls`.
         let angle = starting_angle - idx as f32 * ANGLE_STEP;
 
-        // Get both the closest horizontal and vertical wall
-        // intersections for this angle.
-        let h_dist = horizontal_intersection(player_x, player_y, &horizontal_walls, angle);
-        let v_dist = vertical_intersection(player_x, player_y, &vertical_walls, angle);
+        // Find the first wall along this ray; vertical ones are in "shadow".
+        let (min_dist, shadow, along) = cast_ray(player_x, player_y, angle, horizontal_walls, vertical_walls);
 
-        let (min_dist, shadow) = if h_dist < v_dist {
-            (h_dist, false)
-        } else {
-            (v_dist, true)
-        };
+        // Where the ray struck the wall, to tell if it is part of an exit.
+        let hit_x = player_x + cosf(angle) * min_dist;
+        let hit_y = player_y - sinf(angle) * min_dist;
 
         // Get the minimum of the two distances and
         // "convert" it into a wall height.
@@ -214,138 +357,116 @@ pub fn get_wall_view(
             (WALL_HEIGHT / (min_dist * cosf(angle - player_angle))) as i32,
             min_dist,
             shadow,
+            wall_borders_exit(hit_x, hit_y, shadow, width, height, exits),
+            along,
         );
     }
 
     walls
 }
 
-/// Returns the nearest wall the ray intersects with on a horizontal grid line.
-/// Source: https://github.com/grantshandy/wasm4-raycaster/blob/main/src/lib.rs
-/// Copyright (c) 2023 Grant Handy
-/// MIT License
-fn horizontal_intersection(
-    player_x: f32,
-    player_y: f32,
-    horizontal_walls: &Vec<u16, { HEIGHT + 1 }>,
-    angle: f32,
-) -> f32 {
-    // This tells you if the angle is "facing up"
-    // regardless of how big the angle is.
-    let up = fabsf(floorf(angle / PI) % 2.0) != 0.0;
-
-    // first_y and first_x are the first grid intersections
-    // that the ray intersects with.
-    let first_y = if up {
-        ceilf(player_y) - player_y
+/// Whether a ray cast from one point reaches the other before striking a wall.
+pub fn line_of_sight(
+    from_x: f32,
+    from_y: f32,
+    to_x: f32,
+    to_y: f32,
+    horizontal_walls: &Vec<u32, { MAX_HEIGHT + 1 }>,
+    vertical_walls: &Vec<u32, { MAX_WIDTH + 1 }>,
+) -> bool {
+    let rise = to_y - from_y;
+    let run = to_x - from_x;
+    let angle = -1.0 * atan2f(rise, run);
+
+    let (wall_distance, _, _) = cast_ray(from_x, from_y, angle, horizontal_walls, vertical_walls);
+    distance(rise, run) < wall_distance
+}
+
+/// Whether the wall segment containing a hit point is a side of one of the exit cells.
+fn wall_borders_exit(
+    hit_x: f32,
+    hit_y: f32,
+    vertical: bool,
+    width: usize,
+    height: usize,
+    exits: &[usize]
+) -> bool {
+    // The cells on either side of the wall
+    let (first, second) = if vertical {
+        let line = roundf(hit_x) as i32;
+        let row = floorf(hit_y) as i32;
+        ((line - 1, row), (line, row))
     } else {
-        floorf(player_y) - player_y
+        let line = roundf(hit_y) as i32;
+        let column = floorf(hit_x) as i32;
+        ((column, line - 1), (column, line))
     };
-    let first_x = -first_y / tanf(angle);
-
-    // dy and dx are the "ray extension" values mentioned earlier.
-    let dy = if up { 1.0 } else { -1.0 };
-    let dx = -dy / tanf(angle);
-
-    // next_x and next_y are mutable values which will keep track
-    // of how far away the ray is from the player.
-    let mut next_x = first_x;
-    let mut next_y = first_y;
-
-    // This is the loop where the ray is extended until it hits
-    // the wall. It's not an infinite loop as implied in the
-    // explanation, instead it only goes from 0 to 256.
-    //
-    // This was chosen because if something goes wrong and the
-    // ray never hits a wall (which should never happen) the
-    // loop will eventually break and the game will keep on running.
-    for _ in 0..256 {
-        // current_x and current_y are where the ray is currently
-        // on the map, while next_x and next_y are relative
-        // coordinates, current_x and current_y are absolute
-        // points.
-        let current_x = next_x + player_x;
-        let current_y = if up {
-            next_y + player_y
-        } else {
-            next_y + player_y
-        };
-
-        if point_in_wall(current_y, current_x, &horizontal_walls) {
-            break;
-        }
-
-        // if we didn't hit a wall on this extension add
-        // dx and dy to our current position and keep going.
-        next_x += dx;
-        next_y += dy;
-    }
 
-    // return the distance from next_x and next_y to the player.
-    distance(next_x, next_y)
+    [first, second].iter().any(|&(x, y)| {
+        x >= 0 && y >= 0 && (x as usize) < width && (y as usize) < height &&
+            exits.contains(&(x as usize + y as usize * width))
+    })
 }
 
-/// Returns the nearest wall the ray intersects with on a vertical grid line.
-/// Source: https://github.com/grantshandy/wasm4-raycaster/blob/main/src/lib.rs
-/// Copyright (c) 2023 Grant Handy
-/// MIT License
-fn vertical_intersection(
-    player_x: f32,
-    player_y: f32,
-    vertical_walls: &Vec<u16, { WIDTH + 1 }>,
+/// Casts a ray from (`x`, `y`) at `angle` by stepping from cell to cell across the grid lines
+/// it passes (a digital differential analyzer), until it reaches a wall. Returns how far the
+/// ray went, whether the wall it struck is vertical, and where along that wall (0.0 to 1.0)
+/// it struck. A ray crosses each grid line at most once, so the walk is bounded by the size
+/// of the maze.
+pub fn cast_ray(
+    x: f32,
+    y: f32,
     angle: f32,
-) -> f32 {
-    // This tells you if the angle is "facing up"
-    // regardless of how big the angle is.
-    let right = fabsf(floorf((angle - FRAC_PI_2) / PI) % 2.0) != 0.0;
-
-    // first_y and first_x are the first grid intersections
-    // that the ray intersects with.
-    let first_x = if right {
-        ceilf(player_x) - player_x
-    } else {
-        floorf(player_x) - player_x
-    };
-    let first_y = -tanf(angle) * first_x;
-
-    // dy and dx are the "ray extension" values mentioned earlier.
-    let dx = if right { 1.0 } else { -1.0 };
-    let dy = dx * -tanf(angle);
-
-    // next_x and next_y are mutable values which will keep track
-    // of how far away the ray is from the player.
-    let mut next_x = first_x;
-    let mut next_y = first_y;
-
-    // This is the loop where the ray is extended until it hits
-    // the wall. It's not an infinite loop as implied in the
-    // explanation, instead it only goes from 0 to 256.
-    //
-    // This was chosen because if something goes wrong and the
-    // ray never hits a wall (which should never happen) the
-    // loop will eventually quit and the game will keep on running.
-    for _ in 0..256 {
-        // current_x and current_y are where the ray is currently
-        // on the map, while next_x and next_y are relative
-        // coordinates, current_x and current_y are absolute
-        // points.
-        let current_x = if right {
-            next_x + player_x
+    horizontal_walls: &Vec<u32, { MAX_HEIGHT + 1 }>,
+    vertical_walls: &Vec<u32, { MAX_WIDTH + 1 }>,
+) -> (f32, bool, f32) {
+    let (dir_x, dir_y) = (cosf(angle), -sinf(angle));
+    let mut column = floorf(x) as i32;
+    let mut row = floorf(y) as i32;
+    let step_x = if dir_x > 0.0 { 1 } else if dir_x < 0.0 { -1 } else { 0 };
+    let step_y = if dir_y > 0.0 { 1 } else if dir_y < 0.0 { -1 } else { 0 };
+
+    // How far along the ray it crosses a grid line, worked out from the start every time
+    // so that rounding errors don't pile up over a long ray
+    let crossing = |line: i32, from: f32, dir: f32| if dir != 0.0 { (line as f32 - from) / dir } else { f32::INFINITY };
+    // The next vertical (and horizontal) grid line the ray will cross
+    let mut line_x = if step_x > 0 { column + 1 } else { column };
+    let mut line_y = if step_y > 0 { row + 1 } else { row };
+    let mut next_x = crossing(line_x, x, dir_x);
+    let mut next_y = crossing(line_y, y, dir_y);
+
+    let (width, height) = (vertical_walls.len() - 1, horizontal_walls.len() - 1);
+    let lines = vertical_walls.len() + horizontal_walls.len();
+    for _ in 0..lines {
+        if next_x < next_y {
+            // Crossing into the next column: is there a wall on this side of it?
+            if wall_on(line_x, row, vertical_walls, height) {
+                let along = y + dir_y * next_x;
+                return (next_x, true, along - floorf(along));
+            }
+            column += step_x;
+            line_x += step_x;
+            next_x = crossing(line_x, x, dir_x);
         } else {
-            next_x + player_x
-        };
-        let current_y = next_y + player_y;
-
-        if point_in_wall(current_x, current_y, &vertical_walls) {
-            break;
+            if wall_on(line_y, column, horizontal_walls, width) {
+                let along = x + dir_x * next_y;
+                return (next_y, false, along - floorf(along));
+            }
+            row += step_y;
+            line_y += step_y;
+            next_y = crossing(line_y, y, dir_y);
         }
-
-        // if we didn't hit a wall on this extension add
-        // dx and dy to our current position and keep going.
-        next_x += dx;
-        next_y += dy;
     }
 
-    // return the distance from next_x and next_y to the player.
-    distance(next_x, next_y)
-}
\ No newline at end of file
+    // Only a maze that is not closed in lets a ray out
+    (next_x.min(next_y), next_x < next_y, 0.0)
+}
+
+/// Whether grid line `line` has a wall across `cell`, one of the `cells` the line spans.
+/// Anything beyond the maze counts as wall.
+fn wall_on(line: i32, cell: i32, walls: &[u32], cells: usize) -> bool {
+    if line < 0 || line as usize >= walls.len() || cell < 0 || cell as usize >= cells {
+        return true;
+    }
+    walls[line as usize] & (0b1 << cell) != 0
+}
@@ -67,6 +67,7 @@ pub const SYSTEM_HIDE_GAMEPAD_OVERLAY: u8 = 2;
 pub fn blit(sprite: &[u8], x: i32, y: i32, width: u32, height: u32, flags: u32) {
     unsafe { extern_blit(sprite.as_ptr(), x, y, width, height, flags) }
 }
+#[cfg(not(feature = "headless"))]
 extern "C" {
     #[link_name = "blit"]
     fn extern_blit(sprite: *const u8, x: i32, y: i32, width: u32, height: u32, flags: u32);
@@ -99,6 +100,7 @@ pub fn blit_sub(
         )
     }
 }
+#[cfg(not(feature = "headless"))]
 extern "C" {
     #[link_name = "blitSub"]
     fn extern_blit_sub(
@@ -124,6 +126,7 @@ pub const BLIT_ROTATE: u32 = 8;
 pub fn line(x1: i32, y1: i32, x2: i32, y2: i32) {
     unsafe { extern_line(x1, y1, x2, y2) }
 }
+#[cfg(not(feature = "headless"))]
 extern "C" {
     #[link_name = "line"]
     fn extern_line(x1: i32, y1: i32, x2: i32, y2: i32);
@@ -133,6 +136,7 @@ extern "C" {
 pub fn oval(x: i32, y: i32, width: u32, height: u32) {
     unsafe { extern_oval(x, y, width, height) }
 }
+#[cfg(not(feature = "headless"))]
 extern "C" {
     #[link_name = "oval"]
     fn extern_oval(x: i32, y: i32, width: u32, height: u32);
@@ -142,6 +146,7 @@ extern "C" {
 pub fn rect(x: i32, y: i32, width: u32, height: u32) {
     unsafe { extern_rect(x, y, width, height) }
 }
+#[cfg(not(feature = "headless"))]
 extern "C" {
     #[link_name = "rect"]
     fn extern_rect(x: i32, y: i32, width: u32, height: u32);
@@ -152,6 +157,7 @@ pub fn text<T: AsRef<[u8]>>(text: T, x: i32, y: i32) {
     let text_ref = text.as_ref();
     unsafe { extern_text(text_ref.as_ptr(), text_ref.len(), x, y) }
 }
+#[cfg(not(feature = "headless"))]
 extern "C" {
     #[link_name = "textUtf8"]
     fn extern_text(text: *const u8, length: usize, x: i32, y: i32);
@@ -164,6 +170,7 @@ pub fn vline(x: i32, y: i32, len: u32) {
     }
 }
 
+#[cfg(not(feature = "headless"))]
 extern "C" {
     #[link_name = "vline"]
     fn extern_vline(x: i32, y: i32, len: u32);
@@ -176,6 +183,7 @@ pub fn hline(x: i32, y: i32, len: u32) {
     }
 }
 
+#[cfg(not(feature = "headless"))]
 extern "C" {
     #[link_name = "hline"]
     fn extern_hline(x: i32, y: i32, len: u32);
@@ -191,6 +199,7 @@ extern "C" {
 pub fn tone(frequency: u32, duration: u32, volume: u32, flags: u32) {
     unsafe { extern_tone(frequency, duration, volume, flags) }
 }
+#[cfg(not(feature = "headless"))]
 extern "C" {
     #[link_name = "tone"]
     fn extern_tone(frequency: u32, duration: u32, volume: u32, flags: u32);
@@ -213,6 +222,7 @@ pub const TONE_PAN_RIGHT: u32 = 32;
 // │                                                                           │
 // └───────────────────────────────────────────────────────────────────────────┘
 
+#[cfg(not(feature = "headless"))]
 extern "C" {
     /// Reads up to `size` bytes from persistent storage into the pointer `dest`.
     pub fn diskr(dest: *mut u8, size: u32) -> u32;
@@ -232,7 +242,91 @@ pub fn trace<T: AsRef<str>>(text: T) {
     let text_ref = text.as_ref();
     unsafe { extern_trace(text_ref.as_ptr(), text_ref.len()) }
 }
+#[cfg(not(feature = "headless"))]
 extern "C" {
     #[link_name = "traceUtf8"]
     fn extern_trace(trace: *const u8, length: usize);
 }
+
+
+// ┌───────────────────────────────────────────────────────────────────────────┐
+// │                                                                           │
+// │ Headless Stubs                                                            │
+// │                                                                           │
+// └───────────────────────────────────────────────────────────────────────────┘
+
+// Host builds have no WASM-4 runtime to link against, so the imports above are
+// replaced with no-ops. Persistent storage is kept in memory so that saving and
+// loading still round-trip.
+
+#[cfg(feature = "headless")]
+pub const DISK_SIZE: usize = 1024;
+
+#[cfg(feature = "headless")]
+static mut DISK: [u8; DISK_SIZE] = [0; DISK_SIZE];
+
+#[cfg(feature = "headless")]
+static mut DISK_LENGTH: usize = 0;
+
+#[cfg(feature = "headless")]
+unsafe fn extern_blit(sprite: *const u8, x: i32, y: i32, width: u32, height: u32, flags: u32) {}
+
+#[cfg(feature = "headless")]
+unsafe fn extern_blit_sub(
+    sprite: *const u8,
+    x: i32,
+    y: i32,
+    width: u32,
+    height: u32,
+    src_x: u32,
+    src_y: u32,
+    stride: u32,
+    flags: u32,
+) {}
+
+#[cfg(feature = "headless")]
+unsafe fn extern_line(x1: i32, y1: i32, x2: i32, y2: i32) {}
+
+#[cfg(feature = "headless")]
+unsafe fn extern_oval(x: i32, y: i32, width: u32, height: u32) {}
+
+#[cfg(feature = "headless")]
+unsafe fn extern_rect(x: i32, y: i32, width: u32, height: u32) {}
+
+#[cfg(feature = "headless")]
+unsafe fn extern_text(text: *const u8, length: usize, x: i32, y: i32) {}
+
+#[cfg(feature = "headless")]
+unsafe fn extern_vline(x: i32, y: i32, len: u32) {}
+
+#[cfg(feature = "headless")]
+unsafe fn extern_hline(x: i32, y: i32, len: u32) {}
+
+#[cfg(feature = "headless")]
+unsafe fn extern_tone(frequency: u32, duration: u32, volume: u32, flags: u32) {}
+
+/// Reads up to `size` bytes from the in-memory disk into the pointer `dest`.
+///
+/// # Safety
+/// `dest` must be valid for writes of `size` bytes.
+#[cfg(feature = "headless")]
+pub unsafe fn diskr(dest: *mut u8, size: u32) -> u32 {
+    let length = DISK_LENGTH.min(size as usize);
+    core::ptr::copy_nonoverlapping(core::ptr::addr_of!(DISK) as *const u8, dest, length);
+    length as u32
+}
+
+/// Writes up to `size` bytes from the pointer `src` into the in-memory disk.
+///
+/// # Safety
+/// `src` must be valid for reads of `size` bytes.
+#[cfg(feature = "headless")]
+pub unsafe fn diskw(src: *const u8, size: u32) -> u32 {
+    let length = DISK_SIZE.min(size as usize);
+    core::ptr::copy_nonoverlapping(src, core::ptr::addr_of_mut!(DISK) as *mut u8, length);
+    DISK_LENGTH = length;
+    length as u32
+}
+
+#[cfg(feature = "headless")]
+unsafe fn extern_trace(trace: *const u8, length: usize) {}
@@ -0,0 +1,978 @@
+use maze_racer::arms::{Ammo, Bullet, Weapon};
+use maze_racer::pickups::PickupKind;
+use maze_racer::constants::{
+    BULLETS_PER_PLAYER, BULLET_SPEED, MAX_LIFE, NUM_PICKUPS, NUM_PLAYERS, PICKUP_RESPAWN, PLAYER_RADIUS, PLAYER_WIDTH
+};
+use maze_racer::save::{SaveData, SAVE_SIZE, MAX_HIGH_SCORES};
+use maze_racer::maze::{distance_field, find_neighbors, there_is_a_wall_between, Algorithm, UNREACHABLE};
+use maze_racer::state::{Brain, ControlScheme, Controller, Difficulty, GameMode, MazeSize, Options, State, View};
+use maze_racer::util::sweep_circle;
+use maze_racer::view::{
+    cast_ray, draw_floor_and_ceiling, draw_sprite, fog_level, fogged, get_ammo_view, get_wall_view, in_circle, line_of_sight, FOG
+};
+use maze_racer::textures::{texel, BLOCKS, BRICKS};
+use maze_racer::menu::{cycle, Menu, MenuEvent};
+use maze_racer::wasm4::{BUTTON_1, BUTTON_2, BUTTON_DOWN, BUTTON_LEFT, BUTTON_RIGHT, BUTTON_UP};
+use rand::{rngs::SmallRng, Rng, SeedableRng};
+
+fn new_game(seed: u64) -> State {
+    new_game_of_size(seed, MazeSize::Medium)
+}
+
+fn new_game_of_size(seed: u64, size: MazeSize) -> State {
+    let mut state = State::new();
+    let mut rng = SmallRng::seed_from_u64(seed);
+    state.generate_maze(&mut rng, size, Algorithm::RecursiveBacktracker, 0.0);
+    state
+}
+
+#[test]
+fn maze_is_enclosed() {
+    for size in [MazeSize::Small, MazeSize::Medium, MazeSize::Large] {
+        let state = new_game_of_size(42, size);
+        let (width, height) = size.dimensions();
+        assert_eq!(state.horizontal_walls.len(), height + 1);
+        assert_eq!(state.vertical_walls.len(), width + 1);
+        let full_row = u32::MAX >> (32 - width);
+        let full_column = u32::MAX >> (32 - height);
+        assert_eq!(state.horizontal_walls[0] & full_row, full_row);
+        assert_eq!(state.horizontal_walls[height] & full_row, full_row);
+        assert_eq!(state.vertical_walls[0] & full_column, full_column);
+        assert_eq!(state.vertical_walls[width] & full_column, full_column);
+    }
+}
+
+fn open_neighbors(state: &State, cell: usize) -> Vec<usize> {
+    find_neighbors(cell, state.width, state.height).into_iter()
+        .flatten()
+        .filter(|&n| !there_is_a_wall_between(cell, n, state.width, &state.horizontal_walls, &state.vertical_walls))
+        .collect()
+}
+
+fn count_reachable(state: &State) -> usize {
+    let mut seen = vec![false; state.width * state.height];
+    let mut stack = vec![0];
+    seen[0] = true;
+    let mut count = 0;
+    while let Some(cell) = stack.pop() {
+        count += 1;
+        for n in open_neighbors(state, cell) {
+            if !seen[n] {
+                seen[n] = true;
+                stack.push(n);
+            }
+        }
+    }
+    count
+}
+
+fn count_dead_ends(state: &State) -> usize {
+    (0..state.width * state.height).filter(|&c| open_neighbors(state, c).len() == 1).count()
+}
+
+#[test]
+fn every_algorithm_makes_a_perfect_maze() {
+    let algorithms = [
+        Algorithm::RecursiveBacktracker,
+        Algorithm::Prim,
+        Algorithm::Kruskal,
+        Algorithm::Eller,
+        Algorithm::BinaryTree
+    ];
+    for algorithm in algorithms {
+        for size in [MazeSize::Small, MazeSize::Large] {
+            let mut state = State::new();
+            state.generate_maze(&mut SmallRng::seed_from_u64(8), size, algorithm, 0.0);
+            let cells = state.width * state.height;
+            assert_eq!(count_reachable(&state), cells);
+            // A spanning tree has one fewer passage than cells
+            let openings: usize = (0..cells).map(|c| open_neighbors(&state, c).len()).sum();
+            assert_eq!(openings / 2, cells - 1);
+        }
+    }
+}
+
+#[test]
+fn braiding_removes_dead_ends() {
+    let mut perfect = State::new();
+    perfect.generate_maze(&mut SmallRng::seed_from_u64(4), MazeSize::Large, Algorithm::Prim, 0.0);
+    let mut braided = State::new();
+    braided.generate_maze(&mut SmallRng::seed_from_u64(4), MazeSize::Large, Algorithm::Prim, 1.0);
+    let mut half = State::new();
+    half.generate_maze(&mut SmallRng::seed_from_u64(4), MazeSize::Large, Algorithm::Prim, 0.5);
+    assert_eq!(count_dead_ends(&braided), 0);
+    assert!(count_dead_ends(&half) <= count_dead_ends(&perfect) / 2 + 1);
+    assert!(count_dead_ends(&half) > 0);
+}
+
+#[test]
+fn players_spawn_in_the_corners() {
+    let state = new_game_of_size(1, MazeSize::Large);
+    assert_eq!(state.player_x, [0.5, 31.5, 0.5, 31.5]);
+    assert_eq!(state.player_y, [0.5, 0.5, 31.5, 31.5]);
+    assert_eq!(state.exits.len(), 4);
+}
+
+#[test]
+fn players_stay_in_the_maze() {
+    let mut state = new_game(7);
+    for _ in 0..600 {
+        state.update([BUTTON_UP | BUTTON_RIGHT, 0, 0, 0]);
+        for pidx in 0..NUM_PLAYERS {
+            assert!(state.player_x[pidx] > 0.0 && state.player_x[pidx] < state.width as f32);
+            assert!(state.player_y[pidx] > 0.0 && state.player_y[pidx] < state.height as f32);
+        }
+    }
+}
+
+#[test]
+fn bots_move_toward_player_one() {
+    let mut state = new_game(3);
+    let start = (state.player_x, state.player_y);
+    for _ in 0..120 {
+        state.update([0; NUM_PLAYERS]);
+    }
+    assert_ne!(start, (state.player_x, state.player_y));
+}
+
+#[test]
+fn shooting_spawns_a_bullet() {
+    let mut state = new_game(11);
+    state.update([BUTTON_1, 0, 0, 0]);
+    assert!(state.bullets.iter().any(|b| b.owner == 0));
+}
+
+#[test]
+fn pressing_a_button_takes_over_a_bot() {
+    let mut state = new_game(5);
+    state.drop_in = true;
+    assert!(state.player_controller[2] == Controller::Bot);
+    state.update([0, 0, BUTTON_1, 0]);
+    assert!(state.player_controller[2] == Controller::Human);
+    assert!(state.bullets.iter().any(|b| b.owner == 2));
+
+    // Holding the button does not fire again
+    let fired = state.bullets.iter().filter(|b| b.owner == 2).count();
+    state.update([0, 0, BUTTON_1, 0]);
+    assert_eq!(fired, state.bullets.iter().filter(|b| b.owner == 2).count());
+}
+
+#[test]
+fn slots_keep_the_controller_chosen_for_them() {
+    let players = [Controller::Bot, Controller::Human, Controller::Bot, Controller::Human];
+    let mut state = State::new();
+    state.reset(5, Options { players, ..Options::new() });
+    assert!(state.player_controller == players);
+
+    // Without drop-in a bot's gamepad is ignored
+    state.update([0, 0, BUTTON_1, 0]);
+    assert!(state.player_controller[2] == Controller::Bot);
+    assert!(!state.bullets.iter().any(|b| b.owner == 2));
+}
+
+#[test]
+fn bots_only_hunt_people_they_have_seen() {
+    let players = [Controller::Bot, Controller::Bot, Controller::Human, Controller::Bot];
+    let mut state = State::new();
+    state.reset(5, Options { players, ..Options::new() });
+
+    // Nobody starts out in sight, so every bot wanders
+    state.update([0; NUM_PLAYERS]);
+    for pidx in [0, 1, 3] {
+        assert_eq!(state.target(pidx), None);
+        assert!(state.player_brain[pidx] == Brain::Wander);
+    }
+
+    // Step in front of the first bot; only it gives chase
+    let angle = state.player_angle[0];
+    state.player_x[2] = state.player_x[0] + 0.3 * angle.cos();
+    state.player_y[2] = state.player_y[0] - 0.3 * angle.sin();
+    state.update([0; NUM_PLAYERS]);
+    assert_eq!(state.target(0), Some(2));
+    assert_eq!(state.target(1), None);
+    assert_eq!(state.target(3), None);
+
+    // With nobody left to chase, bots have no target
+    state.player_life[2] = 0;
+    state.update([0; NUM_PLAYERS]);
+    for pidx in [0, 1, 3] {
+        assert_eq!(state.target(pidx), None);
+    }
+}
+
+#[test]
+fn identical_inputs_give_identical_games() {
+    let mut a = new_game(9);
+    let mut b = new_game(9);
+    for frame in 0..600u32 {
+        let gamepad = if frame % 30 == 0 { BUTTON_1 } else { BUTTON_UP };
+        a.update([gamepad, 0, 0, 0]);
+        b.update([gamepad, 0, 0, 0]);
+    }
+    assert_eq!(a.player_x, b.player_x);
+    assert_eq!(a.player_y, b.player_y);
+    assert_eq!(a.player_angle, b.player_angle);
+    assert_eq!(a.player_life, b.player_life);
+    assert!(a.bullets == b.bullets);
+
+    // A bot's dice depend only on the seed, the frame and its slot, so player 1 firing
+    // into the wall (or not) must not change what the bots do
+    let options = Options { size: MazeSize::Small, braid: 1.0, difficulty: Difficulty::Easy, ..Options::new() };
+    let mut a = State::new();
+    let mut b = State::new();
+    a.reset(9, options);
+    b.reset(9, options);
+    a.player_angle[0] = core::f32::consts::FRAC_PI_2;
+    b.player_angle[0] = core::f32::consts::FRAC_PI_2;
+    for frame in 0..600u32 {
+        let gamepad = if frame % 30 == 0 { BUTTON_1 } else { 0 };
+        a.update([gamepad, 0, 0, 0]);
+        b.update([0; NUM_PLAYERS]);
+        let bots_bullets = |state: &State| state.bullets.iter().filter(|b| b.owner != 0).copied().collect::<Vec<_>>();
+        assert!(bots_bullets(&a) == bots_bullets(&b));
+    }
+    assert_eq!(a.player_x[1..], b.player_x[1..]);
+    assert_eq!(a.player_y[1..], b.player_y[1..]);
+    assert_eq!(a.player_life, b.player_life);
+}
+
+#[test]
+fn bots_race_to_the_exit() {
+    let mut state = new_game(21);
+    state.mode = GameMode::Race;
+    assert_eq!(state.exits.len(), 1);
+    for _ in 0..20_000 {
+        state.update([0; NUM_PLAYERS]);
+        if state.winner.is_some() {
+            break;
+        }
+    }
+    let winner = state.winner.expect("a bot should reach the exit");
+    assert_ne!(winner, 0);
+    assert!(state.finish_time[winner].is_some());
+    assert!(state.finish_time[0].is_none());
+}
+
+#[test]
+fn high_scores_survive_a_round_trip() {
+    let mut save = SaveData::new();
+    save.seed = 0xdeadbeef;
+    for score in [50, 300, 120, 300] {
+        save.record(score, GameMode::Deathmatch, MazeSize::Medium);
+    }
+    assert_eq!(save.record(200, GameMode::Race, MazeSize::Large), Some(2));
+    save.controls = ControlScheme::Mouse;
+
+    let loaded = SaveData::from_bytes(&save.to_bytes());
+    assert_eq!(loaded.seed, 0xdeadbeef);
+    assert_eq!(loaded.runs, 5);
+    let scores: Vec<i32> = loaded.high_scores.iter().map(|h| h.score).collect();
+    assert_eq!(scores, [300, 300, 200, 120, 50]);
+    // Ties keep the earlier round first
+    assert_eq!(loaded.high_scores[0].run, 2);
+    assert!(loaded.high_scores[2].mode == GameMode::Race);
+    assert!(loaded.high_scores[2].size == MazeSize::Large);
+    assert!(loaded.controls == ControlScheme::Mouse);
+}
+
+#[test]
+fn high_score_table_keeps_the_best_ten() {
+    let mut save = SaveData::new();
+    for score in 0..MAX_HIGH_SCORES as i32 {
+        save.record(100 + score, GameMode::Deathmatch, MazeSize::Small);
+    }
+    assert_eq!(save.record(5, GameMode::Deathmatch, MazeSize::Small), None);
+    assert_eq!(save.record(500, GameMode::Deathmatch, MazeSize::Small), Some(0));
+    assert_eq!(save.high_scores.len(), MAX_HIGH_SCORES);
+    assert_eq!(save.high_scores.last().unwrap().score, 101);
+}
+
+#[test]
+fn a_disk_with_only_a_seed_has_no_high_scores() {
+    let mut bytes = [0u8; SAVE_SIZE];
+    bytes[0..4].copy_from_slice(&[1, 2, 3, 4]);
+    let save = SaveData::from_bytes(&bytes);
+    assert_eq!(save.seed, u32::from_le_bytes([1, 2, 3, 4]));
+    assert!(save.high_scores.is_empty());
+}
+
+#[test]
+fn menus_wrap_around() {
+    let mut menu = Menu::new();
+    assert!(menu.navigate(BUTTON_UP, 3) == MenuEvent::Nothing);
+    assert_eq!(menu.cursor, 2);
+    assert!(menu.navigate(BUTTON_DOWN, 3) == MenuEvent::Nothing);
+    assert!(menu.navigate(BUTTON_LEFT, 3) == MenuEvent::Change(0, -1));
+    assert!(menu.navigate(BUTTON_1, 3) == MenuEvent::Select(0));
+
+    let sizes = [MazeSize::Small, MazeSize::Medium, MazeSize::Large];
+    assert!(cycle(&sizes, MazeSize::Small, -1) == MazeSize::Large);
+    assert!(cycle(&sizes, MazeSize::Large, 1) == MazeSize::Small);
+}
+
+#[test]
+fn reset_starts_a_fresh_round() {
+    let options = Options { mode: GameMode::Race, size: MazeSize::Large, ..Options::new() };
+    let mut fresh = State::new();
+    fresh.reset(77, options);
+
+    let mut replayed = State::new();
+    replayed.reset(77, Options::new());
+    for _ in 0..300 {
+        replayed.update([BUTTON_1 | BUTTON_UP, 0, 0, 0]);
+        replayed.update([BUTTON_UP, 0, 0, 0]);
+    }
+    replayed.player_life[1] = 0;
+    // Chaining rounds must not run out of room in the maze buffers
+    for _ in 0..5 {
+        replayed.reset(77, options);
+    }
+
+    assert!(replayed.horizontal_walls == fresh.horizontal_walls);
+    assert!(replayed.vertical_walls == fresh.vertical_walls);
+    assert_eq!(replayed.exits, fresh.exits);
+    assert_eq!(replayed.player_x, fresh.player_x);
+    assert_eq!(replayed.player_y, fresh.player_y);
+    assert_eq!(replayed.player_life, fresh.player_life);
+    assert!(replayed.bullets.is_empty());
+    assert!(replayed.mode == GameMode::Race);
+    assert_eq!(replayed.score, fresh.score);
+    assert_eq!(replayed.elapsed(), 0);
+}
+
+#[test]
+fn distance_field_finds_shortest_routes() {
+    let mut state = State::new();
+    state.reset(5, Options { braid: 0.5, ..Options::new() });
+    let mut distances: heapless::Vec<u16, { maze_racer::constants::MAX_CELLS }> = heapless::Vec::new();
+    distance_field(&[0], state.width, state.height, &state.horizontal_walls, &state.vertical_walls, &mut distances);
+
+    // Each cell is exactly one step further than its closest open neighbor
+    assert_eq!(distances[0], 0);
+    for cell in 1..state.width * state.height {
+        assert_ne!(distances[cell], UNREACHABLE, "cell {cell} unreachable");
+        let closest = open_neighbors(&state, cell).into_iter().map(|n| distances[n]).min().unwrap();
+        assert_eq!(distances[cell], closest + 1, "cell {cell}");
+    }
+}
+
+#[test]
+fn bots_never_fire_more_than_their_magazine() {
+    for difficulty in [Difficulty::Easy, Difficulty::Normal, Difficulty::Hard, Difficulty::Nightmare] {
+        let mut state = State::new();
+        state.reset(13, Options { size: MazeSize::Small, braid: 1.0, difficulty, ..Options::new() });
+        let magazine = difficulty.skill().magazine;
+        let mut most_in_flight = 0;
+        // Bots have to come across the player before they open fire
+        for _ in 0..10800 {
+            state.update([0; NUM_PLAYERS]);
+            for bot in 1..NUM_PLAYERS {
+                let in_flight = state.bullets.iter().filter(|b| b.owner == bot).count();
+                most_in_flight = most_in_flight.max(in_flight);
+            }
+        }
+        assert!(most_in_flight <= magazine, "{} fired {most_in_flight}", difficulty.name());
+        assert!(most_in_flight > 0, "{} never fired", difficulty.name());
+    }
+}
+
+#[test]
+fn walls_block_line_of_sight() {
+    let state = new_game(21);
+    let (width, height) = (state.width, state.height);
+    for cell in 0..width * height {
+        let (x, y) = ((cell % width) as f32 + 0.5, (cell / width) as f32 + 0.5);
+        assert!(line_of_sight(x, y, x + 0.2, y - 0.1, &state.horizontal_walls, &state.vertical_walls));
+        for n in find_neighbors(cell, width, height).into_iter().flatten() {
+            let (nx, ny) = ((n % width) as f32 + 0.5, (n / width) as f32 + 0.5);
+            let open = !there_is_a_wall_between(cell, n, width, &state.horizontal_walls, &state.vertical_walls);
+            assert_eq!(line_of_sight(x, y, nx, ny, &state.horizontal_walls, &state.vertical_walls), open, "{cell} to {n}");
+        }
+    }
+}
+
+#[test]
+fn bots_only_fire_at_a_visible_target() {
+    let mut state = State::new();
+    state.reset(8, Options { size: MazeSize::Small, braid: 1.0, difficulty: Difficulty::Nightmare, ..Options::new() });
+    let mut shots = 0;
+    for _ in 0..3600 {
+        let fired: Vec<usize> = (1..NUM_PLAYERS).map(|bot| state.bullets.iter().filter(|b| b.owner == bot).count()).collect();
+        state.update([0; NUM_PLAYERS]);
+        for bot in 1..NUM_PLAYERS {
+            if state.bullets.iter().filter(|b| b.owner == bot).count() > fired[bot - 1] {
+                shots += 1;
+                assert!(line_of_sight(
+                    state.player_x[bot], state.player_y[bot],
+                    state.player_x[0], state.player_y[0],
+                    &state.horizontal_walls, &state.vertical_walls
+                ));
+            }
+        }
+    }
+    assert!(shots > 0);
+}
+
+#[test]
+fn bots_fight_each_other_in_a_free_for_all() {
+    for seed in 0..30 {
+        let mut state = State::new();
+        state.reset(seed, Options { mode: GameMode::FreeForAll, size: MazeSize::Small, braid: 1.0, difficulty: Difficulty::Nightmare, ..Options::new() });
+        // Player 1 sits the round out, so every kill is one bot shooting another
+        state.player_life[0] = 0;
+        for _ in 0..20000 {
+            state.update([0; NUM_PLAYERS]);
+            if state.player_life[1..].iter().any(|&life| life <= 0) {
+                break;
+            }
+        }
+        assert_eq!(state.kills[0], 0, "seed {seed}");
+        assert!(state.kills[1..].iter().sum::<u32>() >= 1, "no bot was killed with seed {seed}");
+        let deaths = state.player_life[1..].iter().filter(|&&life| life <= 0).count() as u32;
+        assert_eq!(state.kills.iter().sum::<u32>(), deaths, "seed {seed}");
+    }
+}
+
+#[test]
+fn wounded_bots_hide_in_a_dead_end() {
+    let mut state = new_game(17);
+    state.player_life[1] = 1;
+    // Nothing lying around to patch it up
+    state.pickups.clear();
+    for _ in 0..6000 {
+        state.update([0; NUM_PLAYERS]);
+    }
+    assert!(state.player_brain[1] == Brain::Flee || state.player_brain[1] == Brain::Engage);
+    let cell = state.player_x[1] as usize + state.player_y[1] as usize * state.width;
+    assert_eq!(open_neighbors(&state, cell).len(), 1);
+}
+
+#[test]
+fn bots_out_of_ammo_wait_to_reload() {
+    let mut state = new_game(6);
+    state.player_ammo[2] = [Ammo::Reloading(10); BULLETS_PER_PLAYER];
+    let start = (state.player_x[2], state.player_y[2]);
+    state.update([0; NUM_PLAYERS]);
+    assert!(state.player_brain[2] == Brain::Reload);
+    for _ in 0..5 {
+        state.update([0; NUM_PLAYERS]);
+    }
+    assert_eq!(start, (state.player_x[2], state.player_y[2]));
+
+    // Back on the move once the magazine is full
+    for _ in 0..60 {
+        state.update([0; NUM_PLAYERS]);
+    }
+    assert!(state.player_brain[2] != Brain::Reload);
+}
+
+#[test]
+fn walking_into_a_wall_slides_along_it() {
+    let mut state = new_game(2);
+    // Head up into the outer wall at a shallow angle, drifting right
+    state.player_angle[0] = std::f32::consts::FRAC_PI_2 - 0.3;
+    for _ in 0..20 {
+        state.update([BUTTON_UP, 0, 0, 0]);
+    }
+    assert!((state.player_y[0] - PLAYER_RADIUS).abs() < 1e-4, "y = {}", state.player_y[0]);
+    assert!(state.player_x[0] > 0.6, "x = {}", state.player_x[0]);
+}
+
+#[test]
+fn players_keep_their_distance_from_walls() {
+    let mut state = new_game_of_size(12, MazeSize::Small);
+    let mut rng = SmallRng::seed_from_u64(12);
+    for frame in 0..3000 {
+        let gamepad = [BUTTON_UP, BUTTON_UP | BUTTON_LEFT, BUTTON_UP | BUTTON_RIGHT, BUTTON_DOWN][(frame / 40 + rng.gen_range(0..2)) % 4];
+        state.update([gamepad, 0, 0, 0]);
+        for pidx in 0..NUM_PLAYERS {
+            let (x, y) = (state.player_x[pidx], state.player_y[pidx]);
+            let cell = x as usize + y as usize * state.width;
+            let (column, row) = (x.floor(), y.floor());
+            let slack = PLAYER_RADIUS - 1e-4;
+            for n in find_neighbors(cell, state.width, state.height).into_iter().flatten() {
+                if there_is_a_wall_between(cell, n, state.width, &state.horizontal_walls, &state.vertical_walls) {
+                    let gap = match n as isize - cell as isize {
+                        -1 => x - column,
+                        1 => column + 1.0 - x,
+                        d if d < 0 => y - row,
+                        _ => row + 1.0 - y
+                    };
+                    assert!(gap >= slack, "player {pidx} is {gap} from a wall");
+                }
+            }
+            assert!(x >= slack && y >= slack);
+            assert!(x <= state.width as f32 - slack && y <= state.height as f32 - slack);
+        }
+    }
+}
+
+#[test]
+fn holding_button_2_strafes() {
+    let mut state = State::new();
+    state.reset(3, Options { controls: ControlScheme::Strafe, ..Options::new() });
+    // Face down the open side of the first cell, then sidestep to the left
+    state.player_angle[0] = 0.0;
+    let (x, y) = (state.player_x[0], state.player_y[0]);
+    let sidestep = if open_neighbors(&state, 0).contains(&state.width) { BUTTON_RIGHT } else { BUTTON_LEFT };
+    for _ in 0..5 {
+        state.update([BUTTON_2 | sidestep, 0, 0, 0]);
+    }
+    assert_eq!(state.player_angle[0], 0.0);
+    assert_eq!(state.player_x[0], x);
+    assert_ne!(state.player_y[0], y);
+
+    // Letting go after strafing keeps the view; a tap on its own switches it
+    state.update([0; NUM_PLAYERS]);
+    assert!(matches!(state.player_view[0], View::FirstPerson));
+    state.update([BUTTON_2, 0, 0, 0]);
+    state.update([0; NUM_PLAYERS]);
+    assert!(matches!(state.player_view[0], View::TopDown));
+}
+
+#[test]
+fn swept_hits_cover_grazing_and_tunneling() {
+    // Head on, touching the near edge of the circle a quarter of the way along
+    assert_eq!(sweep_circle(0.0, 0.0, 2.0, 0.0, 1.0, 0.0, 0.5), Some(0.25));
+    // Grazing exactly along the edge still counts; a hair further out does not
+    assert!(sweep_circle(0.0, 0.5, 2.0, 0.5, 1.0, 0.0, 0.5).is_some());
+    assert!(sweep_circle(0.0, 0.501, 2.0, 0.501, 1.0, 0.0, 0.5).is_none());
+    // A long step straight through the circle hits even though neither end is inside it
+    assert_eq!(sweep_circle(-5.0, 0.0, 5.0, 0.0, 0.0, 0.0, 0.1), Some(0.49));
+    // Stopping short, or starting past it, misses
+    assert!(sweep_circle(0.0, 0.0, 0.4, 0.0, 1.0, 0.0, 0.5).is_none());
+    assert!(sweep_circle(1.6, 0.0, 2.0, 0.0, 1.0, 0.0, 0.5).is_none());
+    // Starting inside is an immediate hit, even without moving
+    assert_eq!(sweep_circle(1.1, 0.1, 1.1, 0.1, 1.0, 0.0, 0.5), Some(0.0));
+    // Clipping the circle diagonally
+    let t = sweep_circle(0.0, 1.0, 1.0, 0.0, 1.0, 1.0, 0.75).unwrap();
+    assert!(t > 0.0 && t < 0.5);
+}
+
+/// Puts player 1 somewhere out of the way and fires a lone bullet from player 2's slot.
+fn shoot_at(state: &mut State, from: (f32, f32), angle: f32, target: (f32, f32)) {
+    state.player_controller = [Controller::Human; NUM_PLAYERS];
+    state.player_x[0] = state.player_x[1];
+    state.player_y[0] = state.player_y[1];
+    state.player_x[2] = target.0;
+    state.player_y[2] = target.1;
+    state.bullets.clear();
+    state.bullets.push(Bullet::new(from.0, from.1, 1, angle, true)).ok();
+}
+
+#[test]
+fn bullets_hit_players_across_cell_boundaries() {
+    let mut state = new_game(31);
+    // Find two cells side by side with no wall between them
+    let (cell, _) = (0..state.width * state.height)
+        .filter(|&c| (c + 1) % state.width != 0)
+        .map(|c| (c, c + 1))
+        .find(|&(a, b)| open_neighbors(&state, a).contains(&b))
+        .unwrap();
+    let (x, y) = ((cell % state.width) as f32, (cell / state.width) as f32);
+
+    // The target stands in the right-hand cell, but its hitbox pokes back into the left one,
+    // where a bullet passes by just inside the edge of the hitbox without ever changing cell
+    let target = (x + 1.1, y + 0.5);
+    shoot_at(&mut state, (x + 0.9, y + 0.9), std::f32::consts::FRAC_PI_2, target);
+    let before = state.player_life[2];
+    for _ in 0..20 {
+        state.update([0; NUM_PLAYERS]);
+    }
+    assert_eq!(state.player_life[2], before - 1);
+    assert!(state.bullets.is_empty());
+
+    // A bullet passing just outside the hitbox does no harm
+    shoot_at(&mut state, (x + 0.9, y + 0.9), std::f32::consts::FRAC_PI_2, (x + 0.92 + PLAYER_WIDTH / 2.0, y + 0.5));
+    for _ in 0..20 {
+        state.update([0; NUM_PLAYERS]);
+    }
+    assert_eq!(state.player_life[2], before - 1);
+}
+
+#[test]
+fn hits_do_not_depend_on_where_the_shooter_stands() {
+    let mut a = new_game(31);
+    let mut b = new_game(31);
+    let (x, y) = (0.5, 0.5);
+    for state in [&mut a, &mut b] {
+        shoot_at(state, (x - 0.3, y), 0.0, (x + 0.2, y + 0.2));
+    }
+    // Player 2 has walked off somewhere else entirely in one of the games
+    b.player_x[1] = b.width as f32 - 0.5;
+    b.player_y[1] = b.height as f32 - 0.5;
+    for _ in 0..(1.0 / BULLET_SPEED) as usize {
+        a.update([0; NUM_PLAYERS]);
+        b.update([0; NUM_PLAYERS]);
+    }
+    assert_eq!(a.player_life[2], 4);
+    assert_eq!(b.player_life[2], 4);
+}
+
+
+#[test]
+fn weapons_are_picked_from_the_top_down_view() {
+    let mut state = new_game(12);
+    state.player_view[0] = View::TopDown;
+    state.update([BUTTON_1, 0, 0, 0]);
+    assert!(state.player_weapon[0] == Weapon::Shotgun);
+    assert!(state.bullets.iter().all(|b| b.owner != 0));
+
+    // The dashboard only has room for what the new weapon holds, and it starts empty
+    let dashboard = get_ammo_view(state.player_ammo[0], state.player_weapon[0]);
+    assert_eq!(dashboard.iter().filter(|&&(_, _, size, _, _)| size > 0).count(), 2);
+    assert!(dashboard.iter().all(|&(_, _, _, _, fill)| fill < 8));
+
+    // Cycling all the way round comes back to the pistol
+    for _ in 0..3 {
+        state.update([0, 0, 0, 0]);
+        state.update([BUTTON_1, 0, 0, 0]);
+    }
+    assert!(state.player_weapon[0] == Weapon::Pistol);
+}
+
+#[test]
+fn a_shotgun_fires_a_spread_of_pellets() {
+    let mut state = new_game(12);
+    state.player_weapon[0] = Weapon::Shotgun;
+    state.update([BUTTON_1, 0, 0, 0]);
+    let pellets: Vec<f32> = state.bullets.iter().filter(|b| b.owner == 0).map(|b| b.angle).collect();
+    assert_eq!(pellets.len(), Weapon::Shotgun.stats().pellets);
+    let spread = pellets.iter().cloned().fold(f32::MIN, f32::max) - pellets.iter().cloned().fold(f32::MAX, f32::min);
+    assert!((spread - Weapon::Shotgun.stats().spread).abs() < 1e-4);
+}
+
+#[test]
+fn heavy_rounds_hit_harder() {
+    let mut state = new_game(31);
+    shoot_at(&mut state, (0.2, 0.5), 0.0, (0.7, 0.7));
+    state.bullets[0] = Bullet::from_weapon(Weapon::Heavy, 0.2, 0.5, 1, 0.0, true);
+    for _ in 0..(1.0 / Weapon::Heavy.stats().speed) as usize {
+        state.update([0; NUM_PLAYERS]);
+    }
+    assert_eq!(state.player_life[2], 5 - Weapon::Heavy.stats().damage);
+    assert!(state.bullets.is_empty());
+}
+
+#[test]
+fn pistol_rounds_drop_short_of_where_a_sniper_reaches() {
+    let mut state = new_game_of_size(8, MazeSize::Large);
+    state.player_controller = [Controller::Human; NUM_PLAYERS];
+    // Knock down the walls along the top row to leave one long corridor, and clear everyone else out of it
+    for column in 1..state.width {
+        state.vertical_walls[column] &= !1;
+    }
+    state.player_y[0] = 2.5;
+    state.player_y[1] = 2.5;
+    let distance = 12.0;
+    assert!(Weapon::Pistol.stats().range < distance && Weapon::Sniper.stats().range > distance);
+
+    for (weapon, hit) in [(Weapon::Pistol, false), (Weapon::Sniper, true)] {
+        state.player_life[2] = 5;
+        state.player_x[2] = 0.5 + distance;
+        state.player_y[2] = 0.5;
+        state.bullets.clear();
+        state.bullets.push(Bullet::from_weapon(weapon, 0.5, 0.5, 1, 0.0, true)).ok();
+        for _ in 0..(distance / weapon.stats().speed) as usize + 10 {
+            state.update([0; NUM_PLAYERS]);
+        }
+        assert_eq!(state.player_life[2] < 5, hit, "{}", weapon.name());
+        assert!(state.bullets.is_empty());
+    }
+}
+
+#[test]
+fn ricochets_bounce_off_walls_until_they_run_out() {
+    let mut state = new_game(31);
+    state.player_controller = [Controller::Human; NUM_PLAYERS];
+    state.player_life = [0, 5, 0, 0];
+    let right = state.width as f32;
+
+    // Straight into the outside wall and back, which uses up the only bounce
+    let mut bullet = Bullet::new(right - 0.5, 0.5, 1, 0.0, true);
+    bullet.bounces = 1;
+    state.bullets.push(bullet).ok();
+    for _ in 0..15 {
+        state.update([0; NUM_PLAYERS]);
+    }
+    assert_eq!(state.bullets.len(), 1);
+    let b = state.bullets[0];
+    assert_eq!(b.bounces, 0);
+    assert!(b.x < right && b.x > right - 0.5);
+    assert!(b.angle.cos() < 0.0);
+
+    // The next wall stops it, as walls stop any bullet without bounces
+    state.bullets.clear();
+    state.bullets.push(Bullet::new(right - 0.5, 0.5, 1, 0.0, true)).ok();
+    for _ in 0..15 {
+        state.update([0; NUM_PLAYERS]);
+    }
+    assert!(state.bullets.is_empty());
+
+    // Into a corner, where both walls turn it around
+    let mut bullet = Bullet::new(0.5, 0.5, 1, 0.75 * std::f32::consts::PI, true);
+    bullet.bounces = 2;
+    state.bullets.push(bullet).ok();
+    for _ in 0..15 {
+        state.update([0; NUM_PLAYERS]);
+    }
+    assert_eq!(state.bullets.len(), 1);
+    let b = state.bullets[0];
+    assert_eq!(b.bounces, 1);
+    assert!(b.angle.cos() > 0.0 && -b.angle.sin() > 0.0);
+    assert!(b.x > 0.0 && b.y > 0.0 && b.x < 1.0 && b.y < 1.0);
+}
+
+
+#[test]
+fn pickups_are_scattered_away_from_the_corners() {
+    let state = new_game(21);
+    assert_eq!(state.pickups.len(), NUM_PICKUPS);
+    for (index, pickup) in state.pickups.iter().enumerate() {
+        assert!(pickup.available());
+        assert!(state.pickups[..index].iter().all(|other| (other.x, other.y) != (pickup.x, pickup.y)));
+        for pidx in 0..NUM_PLAYERS {
+            assert!((state.player_x[pidx], state.player_y[pidx]) != (pickup.x, pickup.y));
+        }
+    }
+}
+
+#[test]
+fn pickups_are_used_up_and_come_back() {
+    let mut state = new_game(21);
+    state.player_controller = [Controller::Human; NUM_PLAYERS];
+    let health = state.pickups.iter().position(|p| p.kind == PickupKind::Health).unwrap();
+    let (x, y) = (state.pickups[health].x, state.pickups[health].y);
+
+    // Nothing happens while it would not help
+    state.player_x[0] = x;
+    state.player_y[0] = y;
+    state.update([0; NUM_PLAYERS]);
+    assert!(state.pickups[health].available());
+
+    state.player_life[0] = 1;
+    state.update([0; NUM_PLAYERS]);
+    assert_eq!(state.player_life[0], 3);
+    assert!(!state.pickups[health].available());
+
+    // Standing on the spot does nothing until it respawns
+    state.player_life[0] = 1;
+    for _ in 0..PICKUP_RESPAWN - 1 {
+        state.update([0; NUM_PLAYERS]);
+    }
+    assert_eq!(state.player_life[0], 1);
+    state.update([0; NUM_PLAYERS]);
+    state.update([0; NUM_PLAYERS]);
+    assert_eq!(state.player_life[0], 3);
+    assert!(state.player_life[0] <= MAX_LIFE);
+}
+
+#[test]
+fn ammo_pickups_reload_and_refill() {
+    let mut state = new_game(21);
+    state.player_controller = [Controller::Human; NUM_PLAYERS];
+    let reload = state.pickups.iter().position(|p| p.kind == PickupKind::Reload).unwrap();
+    state.player_ammo[0] = [Ammo::Reloading(200); BULLETS_PER_PLAYER];
+    state.player_x[0] = state.pickups[reload].x;
+    state.player_y[0] = state.pickups[reload].y;
+    state.update([0; NUM_PLAYERS]);
+    assert!(state.player_ammo[0].iter().all(|&a| a == Ammo::Loaded));
+
+    // Bots only get back as many rounds as their difficulty allows
+    let reload = state.pickups.iter().rposition(|p| p.kind == PickupKind::Reload).unwrap();
+    let bot_magazine = state.difficulty.skill().magazine;
+    state.player_controller[1] = Controller::Bot;
+    state.player_ammo[1] = [Ammo::Reloading(200); BULLETS_PER_PLAYER];
+    state.player_x[1] = state.pickups[reload].x;
+    state.player_y[1] = state.pickups[reload].y;
+    state.update([0; NUM_PLAYERS]);
+    assert!(state.player_ammo[1][..bot_magazine].iter().all(|&a| a == Ammo::Loaded));
+    assert!(state.player_ammo[1][bot_magazine..].iter().all(|&a| a != Ammo::Loaded));
+    state.player_controller[1] = Controller::Human;
+
+    // A spare magazine is swapped in once the last round has been fired
+    let magazine = state.pickups.iter().position(|p| p.kind == PickupKind::Magazine).unwrap();
+    state.player_x[0] = state.pickups[magazine].x;
+    state.player_y[0] = state.pickups[magazine].y;
+    state.update([0; NUM_PLAYERS]);
+    assert_eq!(state.spare_magazines[0], 1);
+    state.player_x[0] = 0.5;
+    state.player_y[0] = 0.5;
+    for _ in 0..BULLETS_PER_PLAYER {
+        state.update([BUTTON_1, 0, 0, 0]);
+        state.update([0; NUM_PLAYERS]);
+    }
+    assert_eq!(state.spare_magazines[0], 0);
+    assert!(state.player_ammo[0].iter().all(|&a| a == Ammo::Loaded));
+}
+
+
+#[test]
+fn walls_report_where_each_ray_struck() {
+    let state = new_game(3);
+    let walls = get_wall_view(0.0, 0.5, 0.5, &state.horizontal_walls, &state.vertical_walls, &state.exits);
+    assert!(walls.iter().all(|&(_, _, _, _, u)| (0.0..1.0).contains(&u)));
+
+    // Looking straight along the middle of a row, the centre ray meets a vertical wall half way along
+    let (_, _, shadow, _, u) = walls[80];
+    assert!(shadow);
+    assert!((u - 0.5).abs() < 1e-3);
+
+    // Bricks are mortared along their edges, and the two faces look different
+    assert_eq!(texel(&BRICKS, 0.0, 0.0), 1);
+    assert_eq!(texel(&BRICKS, 0.5, 0.25), 2);
+    assert!((0..16).any(|i| texel(&BRICKS, 0.5, i as f32 / 16.0) != texel(&BLOCKS, 0.5, i as f32 / 16.0)));
+}
+
+
+fn pixel(framebuffer: &[u8; 6400], x: usize, y: usize) -> u8 {
+    let index = y * 160 + x;
+    (framebuffer[index / 4] >> ((index % 4) * 2)) & 0b11
+}
+
+#[test]
+fn the_floor_is_checkered_cell_by_cell() {
+    // The bottom of the screen shows the floor about half a cell ahead
+    let mut here = [0u8; 6400];
+    let mut next_door = [0u8; 6400];
+    draw_floor_and_ceiling(0.0, 0.2, 0.5, &mut here);
+    draw_floor_and_ceiling(0.0, 1.2, 0.5, &mut next_door);
+    assert_ne!(pixel(&here, 80, 159), pixel(&next_door, 80, 159));
+    // Two cells along, the pattern repeats
+    draw_floor_and_ceiling(0.0, 2.2, 0.5, &mut next_door);
+    assert_eq!(pixel(&here, 80, 159), pixel(&next_door, 80, 159));
+
+    // The ceiling is not the floor turned upside down
+    assert!((0..80).any(|y| (0..160).any(|x| pixel(&here, x, y) != pixel(&here, x, 159 - y))));
+}
+
+
+#[test]
+fn fog_thickens_with_distance() {
+    assert_eq!(fog_level(0.5), 0);
+    assert_eq!(fog_level(100.0), 16);
+    let mut last = 0;
+    for step in 0..200 {
+        let level = fog_level(step as f32 / 10.0);
+        assert!(level >= last);
+        last = level;
+    }
+
+    // Each level gives one more pixel in every 4x4 block over to the fog
+    for level in 0..=16 {
+        let foggy = (0..4).flat_map(|y| (0..4).map(move |x| (x, y)))
+            .filter(|&(x, y)| fogged(x + 8, y + 4, 3, level) == FOG)
+            .count();
+        assert_eq!(foggy, level as usize);
+    }
+
+    // Something drawn close by is left alone, and far away it disappears
+    let mut framebuffer = [0xff; 6400];
+    let open = [f32::MAX; 160];
+    draw_sprite(&mut framebuffer, &open, 20, 20, 8, 8, 1.0, |_, _| Some(2));
+    assert!((20..28).all(|y| (20..28).all(|x| pixel(&framebuffer, x, y) == 2)));
+    draw_sprite(&mut framebuffer, &open, 20, 20, 8, 8, 100.0, |_, _| Some(2));
+    assert!((20..28).all(|y| (20..28).all(|x| pixel(&framebuffer, x, y) == FOG)));
+    assert_eq!(pixel(&framebuffer, 28, 20), 3);
+}
+
+#[test]
+fn sprites_are_clipped_column_by_column() {
+    // A wall covers the middle of the screen, closer than the sprite
+    let mut depth = [10.0; 160];
+    depth[70..90].fill(1.0);
+    let mut framebuffer = [0u8; 6400];
+    draw_sprite(&mut framebuffer, &depth, 60, 40, 40, 10, 2.0, |_, _| Some(3));
+    for x in 60..100 {
+        let hidden = (70..90).contains(&x);
+        assert_eq!(pixel(&framebuffer, x, 45) == 3, !hidden, "column {x}");
+    }
+
+    // A sprite hanging off either side of the screen keeps the part that is on it
+    let mut framebuffer = [0u8; 6400];
+    draw_sprite(&mut framebuffer, &depth, -5, 0, 10, 4, 2.0, |_, _| Some(3));
+    draw_sprite(&mut framebuffer, &depth, 155, 156, 10, 10, 2.0, |_, _| Some(3));
+    assert!((0..5).all(|x| pixel(&framebuffer, x, 0) == 3));
+    assert!((155..160).all(|x| pixel(&framebuffer, x, 159) == 3));
+    assert_eq!(pixel(&framebuffer, 5, 0), 0);
+    assert_eq!(pixel(&framebuffer, 0, 4), 0);
+
+    // See-through pixels leave what was there
+    let mut framebuffer = [0u8; 6400];
+    draw_sprite(&mut framebuffer, &depth, 0, 100, 8, 8, 2.0, |x, y| in_circle(x, y, 8).then_some(3));
+    assert_eq!(pixel(&framebuffer, 0, 100), 0);
+    assert_eq!(pixel(&framebuffer, 4, 104), 3);
+}
+
+
+#[test]
+fn rays_stop_at_the_first_wall() {
+    use core::f32::consts::{FRAC_1_SQRT_2, FRAC_PI_2, FRAC_PI_4, PI};
+
+    // An empty 4 by 3 room, where every distance can be worked out by hand
+    let mut state = new_game_of_size(8, MazeSize::Small);
+    state.horizontal_walls.clear();
+    state.vertical_walls.clear();
+    state.horizontal_walls.extend([0b1111, 0, 0, 0b1111]);
+    state.vertical_walls.extend([0b111, 0, 0, 0, 0b111]);
+    let room = |x: f32, y: f32, angle: f32| cast_ray(x, y, angle, &state.horizontal_walls, &state.vertical_walls);
+    let close = |a: f32, b: f32| (a - b).abs() < 1e-5;
+
+    let (reach, vertical, along) = room(2.0, 1.5, 0.0);
+    assert!(close(reach, 2.0) && vertical && close(along, 0.5));
+    let (reach, vertical, along) = room(2.0, 1.5, PI);
+    assert!(close(reach, 2.0) && vertical && close(along, 0.5));
+    let (reach, vertical, along) = room(2.25, 1.5, FRAC_PI_2);
+    assert!(close(reach, 1.5) && !vertical && close(along, 0.25));
+    let (reach, vertical, along) = room(2.0, 1.5, FRAC_PI_4);
+    assert!(close(reach, 1.5 / FRAC_1_SQRT_2) && !vertical && close(along, 0.5));
+
+    // A wall across the middle of the room, on the vertical line x = 2 in row 1 only
+    state.vertical_walls[2] = 0b010;
+    let room = |x: f32, y: f32, angle: f32| cast_ray(x, y, angle, &state.horizontal_walls, &state.vertical_walls);
+    let (reach, vertical, along) = room(0.5, 1.25, 0.0);
+    assert!(close(reach, 1.5) && vertical && close(along, 0.25));
+    let (reach, _, _) = room(0.5, 0.5, 0.0);
+    assert!(close(reach, 3.5));
+
+    // In a real maze, check each ray against every grid line it crosses, with each crossing
+    // worked out from the start of the ray rather than by stepping along it
+    let state = new_game_of_size(8, MazeSize::Large);
+    let (width, height) = (state.width as f64, state.height as f64);
+    let mut rng = SmallRng::seed_from_u64(8);
+    for _ in 0..500 {
+        let x = rng.gen_range(0.1..state.width as f32 - 0.1);
+        let y = rng.gen_range(0.1..state.height as f32 - 0.1);
+        let angle = rng.gen_range(0.0..std::f32::consts::TAU);
+        let (reach, vertical, along) = cast_ray(x, y, angle, &state.horizontal_walls, &state.vertical_walls);
+
+        let (x, y, dir_x, dir_y) = (x as f64, y as f64, angle.cos() as f64, -angle.sin() as f64);
+        let mut nearest_vertical = f64::INFINITY;
+        for line in 0..=state.width {
+            let t = (line as f64 - x) / dir_x;
+            let row = (y + dir_y * t).floor();
+            let blocked = row < 0.0 || row >= height || state.vertical_walls[line] & (1 << row as u32) != 0;
+            if t > 0.0 && blocked {
+                nearest_vertical = nearest_vertical.min(t);
+            }
+        }
+        let mut nearest_horizontal = f64::INFINITY;
+        for line in 0..=state.height {
+            let t = (line as f64 - y) / dir_y;
+            let column = (x + dir_x * t).floor();
+            let blocked = column < 0.0 || column >= width || state.horizontal_walls[line] & (1 << column as u32) != 0;
+            if t > 0.0 && blocked {
+                nearest_horizontal = nearest_horizontal.min(t);
+            }
+        }
+
+        let expected = nearest_vertical.min(nearest_horizontal);
+        assert!((reach as f64 - expected).abs() < 1e-4, "{reach} vs {expected}");
+        // Rays that graze a corner may fairly report either wall
+        if (nearest_vertical - nearest_horizontal).abs() > 1e-3 {
+            assert_eq!(vertical, nearest_vertical < nearest_horizontal);
+            let hit = if vertical { y + dir_y * expected } else { x + dir_x * expected };
+            assert!((along as f64 - hit.fract()).abs() < 1e-3);
+        }
+    }
+}